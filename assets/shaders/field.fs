@@ -0,0 +1,96 @@
+// Scalar field colouring. `values` holds one float per cell, `texture0` (the drawn texture) the
+// flat colour of the cells that are not fluid with alpha 1, and alpha 0 on fluid cells.
+// Written in GLSL 100 with `VARYING` inputs and `finalColor` as output, the version header
+// defining them is added when loading.
+
+VARYING vec2 fragTexCoord;
+VARYING vec4 fragColor;
+
+uniform sampler2D texture0;
+uniform sampler2D values;
+// 256 x 1 colour map, level 0 on the left
+uniform sampler2D palette;
+
+uniform vec2 gridSize;
+// Values at level 0 and 1
+uniform vec2 range;
+uniform int logScale;
+uniform int symmetric;
+uniform float logDecades;
+// Interpolate between the cell centres instead of showing flat cells
+uniform int smoothing;
+// Level difference between two isolines, 0 without isolines
+uniform float contourSpacing;
+uniform float pixelsPerCell;
+
+// Same mapping as ColorScale::level
+float logLevel(float magnitude, float top) {
+    float bottom = top * pow(10.0, -logDecades);
+    if (magnitude <= bottom) return 0.0;
+    return min(log(magnitude / bottom) / (logDecades * log(10.0)), 1.0);
+}
+
+float valueLevel(float value) {
+    if (range.y <= range.x) return 0.5;
+    if (logScale == 0) return clamp((value - range.x) / (range.y - range.x), 0.0, 1.0);
+    if (symmetric != 0) {
+        float top = max(abs(range.x), abs(range.y));
+        return 0.5 + 0.5 * sign(value) * logLevel(abs(value), top);
+    }
+    if (range.y <= 0.0) return 0.0;
+    float bottom = max(range.x, range.y * pow(10.0, -logDecades));
+    if (value <= bottom) return 0.0;
+    return log(value / bottom) / log(range.y / bottom);
+}
+
+// Level of a cell, `fluid` being 1 for fluid cells and 0 otherwise
+float cellLevel(vec2 cell, out float fluid) {
+    vec2 coordinates = (clamp(cell, vec2(0.0), gridSize - 1.0) + 0.5) / gridSize;
+    fluid = 1.0 - texture2D(texture0, coordinates).a;
+    return valueLevel(texture2D(values, coordinates).r);
+}
+
+void main() {
+    vec2 position = fragTexCoord * gridSize;
+    vec2 cell = floor(position);
+    vec4 cellColor = texture2D(texture0, (cell + 0.5) / gridSize);
+    if (cellColor.a > 0.5) {
+        finalColor = vec4(cellColor.rgb, 1.0);
+        return;
+    }
+
+    // Bilinear interpolation between the centres of the four closest fluid cells. The cell under
+    // the fragment is one of them with a weight of at least 1/4.
+    vec2 corner = floor(position - 0.5);
+    vec2 weight = position - 0.5 - corner;
+    float fluid00;
+    float fluid10;
+    float fluid01;
+    float fluid11;
+    float level00 = cellLevel(corner, fluid00);
+    float level10 = cellLevel(corner + vec2(1.0, 0.0), fluid10);
+    float level01 = cellLevel(corner + vec2(0.0, 1.0), fluid01);
+    float level11 = cellLevel(corner + vec2(1.0, 1.0), fluid11);
+    float weight00 = (1.0 - weight.x) * (1.0 - weight.y) * fluid00;
+    float weight10 = weight.x * (1.0 - weight.y) * fluid10;
+    float weight01 = (1.0 - weight.x) * weight.y * fluid01;
+    float weight11 = weight.x * weight.y * fluid11;
+    float interpolatedLevel = (level00 * weight00 + level10 * weight10 + level01 * weight01 + level11 * weight11)
+        / (weight00 + weight10 + weight01 + weight11);
+
+    float cellFluid;
+    float displayLevel = smoothing != 0 ? interpolatedLevel : cellLevel(cell, cellFluid);
+    vec3 color = texture2D(palette, vec2((displayLevel * 255.0 + 0.5) / 256.0, 0.5)).rgb;
+
+    // Isolines about one pixel wide, where the four cells are fluid and the gradient is known
+    if (contourSpacing > 0.0 && fluid00 * fluid10 * fluid01 * fluid11 > 0.5) {
+        vec2 gradient = vec2(
+            mix(level10 - level00, level11 - level01, weight.y),
+            mix(level01 - level00, level11 - level10, weight.x));
+        float band = interpolatedLevel / contourSpacing;
+        float distanceToLine = abs(fract(band + 0.5) - 0.5) * contourSpacing;
+        float pixelDistance = distanceToLine / max(length(gradient), 1e-6) * pixelsPerCell;
+        color = mix(vec3(0.0), color, smoothstep(0.5, 1.5, pixelDistance));
+    }
+    finalColor = vec4(color, 1.0);
+}
@@ -1,4 +1,5 @@
 use raylib::prelude::*;
+use serde::Deserialize;
 
 pub static COLOR_RED: Color = Color::new(232, 57, 53, 255);
 pub static COLOR_GREEN: Color = Color::new(54, 184, 62, 255);
@@ -15,10 +16,10 @@ pub fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> Color {
     let mut b = lightness;
 
     if saturation != 0.0 {
-        let q = if lightness > 0.5 {
+        let q = if lightness < 0.5 {
             lightness * (1.0 + saturation)
         } else {
-            1.0 + saturation - lightness * saturation
+            lightness + saturation - lightness * saturation
         };
         let p = 2.0 * lightness - q;
         r = hue_to_rgb(p, q, hue + 1.0/3.0);
@@ -37,3 +38,93 @@ fn hue_to_rgb(p: f64, q: f64, mut t: f64) -> f64 {
     if t < 2.0/3.0 { return p + (q - p) * (2.0/3.0 - t) * 6.0; }
     p
 }
+
+/// Colour map of the scalar fields, from level 0 (lowest value) to level 1 (highest value)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Colormap {
+    /// Perceptually uniform, dark blue to yellow
+    #[default]
+    Viridis,
+    /// Perceptually uniform, black to pale yellow
+    Inferno,
+    /// Diverging blue to red through grey, for signed values centred on zero
+    Coolwarm,
+    /// Hue wheel from blue to red
+    Rainbow,
+}
+
+pub const COLORMAPS: [Colormap; 4] = [Colormap::Viridis, Colormap::Inferno, Colormap::Coolwarm, Colormap::Rainbow];
+
+// Colours at evenly spaced levels, sampled from the matplotlib colour maps
+const VIRIDIS: [(u8, u8, u8); 9] = [
+    (68, 1, 84),
+    (71, 45, 123),
+    (59, 82, 139),
+    (44, 114, 142),
+    (33, 145, 140),
+    (40, 174, 128),
+    (94, 201, 98),
+    (173, 220, 48),
+    (253, 231, 37),
+];
+const INFERNO: [(u8, u8, u8); 9] = [
+    (0, 0, 4),
+    (31, 12, 72),
+    (85, 15, 109),
+    (136, 34, 106),
+    (186, 54, 85),
+    (227, 89, 51),
+    (249, 142, 9),
+    (248, 201, 50),
+    (252, 255, 164),
+];
+const COOLWARM: [(u8, u8, u8); 9] = [
+    (59, 76, 192),
+    (98, 130, 234),
+    (141, 176, 254),
+    (184, 208, 249),
+    (221, 221, 221),
+    (245, 196, 173),
+    (244, 154, 123),
+    (222, 96, 77),
+    (180, 4, 38),
+];
+
+impl Colormap {
+    pub fn from_name(name: &str) -> Option<Self> {
+        COLORMAPS.into_iter().find(|colormap| colormap.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Viridis => "viridis",
+            Colormap::Inferno => "inferno",
+            Colormap::Coolwarm => "coolwarm",
+            Colormap::Rainbow => "rainbow",
+        }
+    }
+
+    /// The colour map after this one in `COLORMAPS`, wrapping around
+    pub fn next(self) -> Self {
+        let index = COLORMAPS.iter().position(|colormap| *colormap == self).unwrap_or(0);
+        COLORMAPS[(index + 1) % COLORMAPS.len()]
+    }
+
+    /// Colour at `level`, clamped to [0, 1]
+    pub fn color(self, level: f64) -> Color {
+        let level = if level.is_nan() { 0.0 } else { level.clamp(0.0, 1.0) };
+        let stops = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+            Colormap::Coolwarm => &COOLWARM,
+            Colormap::Rainbow => return hsl_to_rgb((1.0 - level) * 4.0 / 6.0, 1.0, 0.5),
+        };
+        let position = level * (stops.len() - 1) as f64;
+        let index = (position as usize).min(stops.len() - 2);
+        let ratio = position - index as f64;
+        let blend = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * ratio).round() as u8;
+        let (from, to) = (stops[index], stops[index + 1]);
+        Color::new(blend(from.0, to.0), blend(from.1, to.1), blend(from.2, to.2), 255)
+    }
+}
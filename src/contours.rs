@@ -0,0 +1,289 @@
+use crate::colors::*;
+use crate::field_image::{format_value, ColorScale};
+use crate::fluid_engine::*;
+use crate::logging::EXPORT;
+use crate::recording::next_free_path;
+use crate::view_camera::ViewCamera;
+use raylib::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Intervals the colour scale is split into when the levels are not given, cycled with `J`
+const SPACED_LEVEL_COUNTS: [usize; 3] = [5, 10, 20];
+const LINE_THICKNESS: f32 = 1.5;
+const LABEL_FONT_SIZE: i32 = 14;
+/// Lines shorter than this on screen (pixels) are not labelled
+const LABEL_MIN_LENGTH: f32 = 80.0;
+
+/// Edge between two neighbouring cell centres: `(x_id, y_id, 0)` joins `(x_id, y_id)` to
+/// `(x_id + 1, y_id)` and `(x_id, y_id, 1)` joins it to `(x_id, y_id + 1)`
+type EdgeKey = (usize, usize, u8);
+
+/// Isolines of one level
+pub struct Contour {
+    pub level: f64,
+    /// Polylines through the fluid cells (metres), closed ones end on their first point
+    pub lines: Vec<Vec<(f64, f64)>>,
+}
+
+/// Isolines of `values` (indexed like `FluidDomain::fluid_grid`) at every level, by marching
+/// squares over the cell centres. Squares with a corner outside the fluid are skipped, so the
+/// lines stop one half cell away from walls and air.
+pub fn extract_contours(fluid_domain: &FluidDomain, values: &[Vec<f64>], levels: &[f64]) -> Vec<Contour> {
+    levels
+        .iter()
+        .map(|&level| Contour {
+            level,
+            lines: extract_isolines(fluid_domain, values, level),
+        })
+        .collect()
+}
+
+fn extract_isolines(fluid_domain: &FluidDomain, values: &[Vec<f64>], level: f64) -> Vec<Vec<(f64, f64)>> {
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    let spacing = fluid_domain.grid_spacing;
+    let is_fluid = |x_id: usize, y_id: usize| {
+        fluid_domain.fluid_grid[x_id][y_id].state == CellState::Fluid && values[x_id][y_id].is_finite()
+    };
+
+    let mut crossings: HashMap<EdgeKey, (f64, f64)> = HashMap::new();
+    let mut segments: Vec<(EdgeKey, EdgeKey)> = Vec::new();
+    for x_id in 0..grid_size_x.saturating_sub(1) {
+        for y_id in 0..grid_size_y.saturating_sub(1) {
+            let corners = [(x_id, y_id), (x_id + 1, y_id), (x_id + 1, y_id + 1), (x_id, y_id + 1)];
+            if !corners.iter().all(|&(x_id, y_id)| is_fluid(x_id, y_id)) {
+                continue;
+            }
+            let corner_values = corners.map(|(x_id, y_id)| values[x_id][y_id]);
+            let above = corner_values.map(|value| value >= level);
+            // Top, right, bottom and left edges, each between corners `edge_id` and `edge_id + 1`
+            let edges: [EdgeKey; 4] = [(x_id, y_id, 0), (x_id + 1, y_id, 1), (x_id, y_id + 1, 0), (x_id, y_id, 1)];
+            let mut crossed_edges = Vec::with_capacity(4);
+            for (edge_id, edge) in edges.iter().enumerate() {
+                let (start, end) = (edge_id, (edge_id + 1) % 4);
+                if above[start] == above[end] {
+                    continue;
+                }
+                let t = (level - corner_values[start]) / (corner_values[end] - corner_values[start]);
+                let position = |corner: usize| (corners[corner].0 as f64 + 0.5, corners[corner].1 as f64 + 0.5);
+                let (start_position, end_position) = (position(start), position(end));
+                crossings.entry(*edge).or_insert((
+                    (start_position.0 + t * (end_position.0 - start_position.0)) * spacing,
+                    (start_position.1 + t * (end_position.1 - start_position.1)) * spacing,
+                ));
+                crossed_edges.push(edge_id);
+            }
+
+            match crossed_edges[..] {
+                [first, second] => segments.push((edges[first], edges[second])),
+                // Saddle: the centre value decides which pair of opposite corners is connected
+                [_, _, _, _] => {
+                    let center_above = corner_values.iter().sum::<f64>() / 4.0 >= level;
+                    let [top, right, bottom, left] = edges;
+                    if center_above == above[0] {
+                        segments.push((top, right));
+                        segments.push((bottom, left));
+                    } else {
+                        segments.push((left, top));
+                        segments.push((right, bottom));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    join_segments(&segments)
+        .into_iter()
+        .map(|line| line.iter().map(|edge| crossings[edge]).collect())
+        .collect()
+}
+
+/// Chain segments sharing an edge into polylines, open ones from one of their ends
+fn join_segments(segments: &[(EdgeKey, EdgeKey)]) -> Vec<Vec<EdgeKey>> {
+    let mut edge_segments: HashMap<EdgeKey, Vec<usize>> = HashMap::new();
+    for (segment_id, (start, end)) in segments.iter().enumerate() {
+        edge_segments.entry(*start).or_default().push(segment_id);
+        edge_segments.entry(*end).or_default().push(segment_id);
+    }
+
+    let mut visited = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    let mut trace = |start_segment: usize, start_edge: EdgeKey, visited: &mut Vec<bool>| {
+        let mut line = vec![start_edge];
+        let (mut segment_id, mut edge) = (start_segment, start_edge);
+        loop {
+            visited[segment_id] = true;
+            let (start, end) = segments[segment_id];
+            edge = if start == edge { end } else { start };
+            line.push(edge);
+            match edge_segments[&edge].iter().find(|&&next_id| !visited[next_id]) {
+                Some(&next_id) => segment_id = next_id,
+                None => break,
+            }
+        }
+        lines.push(line);
+    };
+
+    for (segment_id, (start, end)) in segments.iter().enumerate() {
+        if visited[segment_id] {
+            continue;
+        }
+        if edge_segments[start].len() == 1 {
+            trace(segment_id, *start, &mut visited);
+        } else if edge_segments[end].len() == 1 {
+            trace(segment_id, *end, &mut visited);
+        }
+    }
+    // What is left only holds closed lines
+    for (segment_id, (start, _)) in segments.iter().enumerate() {
+        if !visited[segment_id] {
+            trace(segment_id, *start, &mut visited);
+        }
+    }
+    lines
+}
+
+/// Write every contour as a point list, positions in metres.
+/// Columns: level, line, point, x, y
+pub fn write_contours_csv(contours: &[Contour], path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "level,line,point,x,y")?;
+    for contour in contours.iter() {
+        for (line_id, line) in contour.lines.iter().enumerate() {
+            for (point_id, (x, y)) in line.iter().enumerate() {
+                writeln!(writer, "{},{},{},{},{}", contour.level, line_id, point_id, x, y)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Levels splitting the colour map into `count` intervals of equal length, so they follow the
+/// log scale when it is on
+pub fn spaced_levels(color_scale: &ColorScale, count: usize) -> Vec<f64> {
+    (1..count)
+        .map(|level_id| color_scale.value_at(level_id as f64 / count as f64))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ContourDisplay {
+    Hidden,
+    Lines,
+    Labelled,
+}
+
+/// Isolines of the displayed field drawn over the scene, at the levels of the scene file or
+/// evenly spaced across the colour scale.
+pub struct ContourOverlay {
+    display: ContourDisplay,
+    /// Levels chosen by the scene, spaced levels are used when empty
+    levels: Vec<f64>,
+    spaced_count_index: usize,
+    contours: Vec<Contour>,
+    /// Exports go to `<export_prefix>_contours_<index>.csv`
+    export_prefix: String,
+}
+impl ContourOverlay {
+    pub fn new(export_prefix: &str) -> Self {
+        ContourOverlay {
+            display: ContourDisplay::Hidden,
+            levels: Vec::new(),
+            spaced_count_index: 1,
+            contours: Vec::new(),
+            export_prefix: export_prefix.to_string(),
+        }
+    }
+
+    /// Show labelled contours at `levels` instead of spaced levels
+    pub fn set_levels(&mut self, levels: Vec<f64>) {
+        self.display = ContourDisplay::Labelled;
+        self.levels = levels;
+    }
+
+    /// `Y` cycles hidden, lines and labelled lines, `J` the number of spaced levels and
+    /// `Shift + Y` exports the contours
+    pub fn update(&mut self, rl_handle: &RaylibHandle) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_Y) {
+            if rl_handle.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
+                let path = next_free_path(&format!("{}_contours", self.export_prefix), ".csv");
+                match write_contours_csv(&self.contours, &path) {
+                    Ok(()) => log::info!(target: EXPORT, "Contours written to {}", path.display()),
+                    Err(error) => log::error!(target: EXPORT, "Unable to write {}: {}", path.display(), error),
+                }
+            } else {
+                self.display = match self.display {
+                    ContourDisplay::Hidden => ContourDisplay::Lines,
+                    ContourDisplay::Lines => ContourDisplay::Labelled,
+                    ContourDisplay::Labelled => ContourDisplay::Hidden,
+                };
+            }
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_J) {
+            self.spaced_count_index = (self.spaced_count_index + 1) % SPACED_LEVEL_COUNTS.len();
+        }
+    }
+
+    /// Extract the contours of the displayed field `values` (see `field_values`), `color_scale`
+    /// being already fitted to them. Nothing is shown without a field.
+    pub fn compute(&mut self, fluid_domain: &FluidDomain, values: Option<&[Vec<f64>]>, color_scale: &ColorScale) {
+        self.contours.clear();
+        let Some(values) = values else {
+            return;
+        };
+        if self.display == ContourDisplay::Hidden {
+            return;
+        }
+        let levels = if self.levels.is_empty() {
+            spaced_levels(color_scale, SPACED_LEVEL_COUNTS[self.spaced_count_index])
+        } else {
+            self.levels.clone()
+        };
+        self.contours = extract_contours(fluid_domain, values, &levels);
+    }
+
+    /// Contours as anti-aliased lines (with the window multisampling), labelled at the middle
+    /// of the lines long enough on screen
+    pub fn draw(&self, rl_handle: &mut RaylibDrawHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        let to_screen = |point: &(f64, f64)| {
+            view.grid_to_screen((point.0 / fluid_domain.grid_spacing, point.1 / fluid_domain.grid_spacing))
+        };
+        for contour in self.contours.iter() {
+            for line in contour.lines.iter() {
+                let points: Vec<Vector2> = line.iter().map(to_screen).collect();
+                for pair in points.windows(2) {
+                    rl_handle.draw_line_ex(pair[0], pair[1], LINE_THICKNESS, COLOR_BLACK);
+                }
+                if self.display == ContourDisplay::Labelled {
+                    draw_label(rl_handle, &points, &format_value(contour.level));
+                }
+            }
+        }
+    }
+}
+
+/// `label` on a light box at half the length of `points`
+fn draw_label(rl_handle: &mut RaylibDrawHandle, points: &[Vector2], label: &str) {
+    let length: f32 = points.windows(2).map(|pair| (pair[1] - pair[0]).length()).sum();
+    if length < LABEL_MIN_LENGTH {
+        return;
+    }
+    let mut remaining = length / 2.0;
+    let mut position = points[0];
+    for pair in points.windows(2) {
+        let segment_length = (pair[1] - pair[0]).length();
+        if remaining <= segment_length {
+            position = pair[0].lerp(pair[1], remaining / segment_length.max(f32::EPSILON));
+            break;
+        }
+        remaining -= segment_length;
+    }
+
+    let width = rl_handle.measure_text(label, LABEL_FONT_SIZE);
+    let (x, y) = (position.x as i32 - width / 2, position.y as i32 - LABEL_FONT_SIZE / 2);
+    rl_handle.draw_rectangle(x - 2, y - 1, width + 4, LABEL_FONT_SIZE + 2, COLOR_LIGHT);
+    rl_handle.draw_text(label, x, y, LABEL_FONT_SIZE, COLOR_BLACK);
+}
@@ -0,0 +1,121 @@
+use crate::colors::*;
+use crate::fluid_engine::*;
+use raylib::prelude::*;
+use std::path::Path;
+
+/// What a mask pixel stands for, matched to the closest colour of the palette
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MaskPixel {
+    Wall,
+    Fluid,
+    Inflow,
+    Outflow,
+    /// Fluid cell where dye is injected at every step
+    DyeSource,
+}
+impl MaskPixel {
+    pub fn from_color(color: Color) -> Self {
+        // Transparent pixels are left to the fluid
+        if color.a < 128 {
+            return MaskPixel::Fluid;
+        }
+        let distance = |reference: &Color| {
+            (color.r as i32 - reference.r as i32).pow(2)
+                + (color.g as i32 - reference.g as i32).pow(2)
+                + (color.b as i32 - reference.b as i32).pow(2)
+        };
+        let palette = [
+            (MaskPixel::Wall, COLOR_BLACK),
+            (MaskPixel::Fluid, COLOR_WHITE),
+            (MaskPixel::Inflow, COLOR_GREEN),
+            (MaskPixel::Outflow, COLOR_RED),
+            (MaskPixel::DyeSource, COLOR_BLUE),
+        ];
+        palette
+            .iter()
+            .min_by_key(|(_, reference)| distance(reference))
+            .map(|(pixel, _)| *pixel)
+            .unwrap()
+    }
+
+    pub fn cell_state(self) -> CellState {
+        match self {
+            MaskPixel::Wall => CellState::Wall,
+            MaskPixel::Fluid | MaskPixel::DyeSource => CellState::Fluid,
+            MaskPixel::Inflow => CellState::Inflow,
+            MaskPixel::Outflow => CellState::Outflow,
+        }
+    }
+}
+
+/// Domain geometry painted in an image, one pixel per cell.
+///
+/// Black is wall, white is fluid, green is inflow, red is outflow and blue is a dye source.
+#[derive(Clone)]
+pub struct DomainMask {
+    pub pixels: Vec<Vec<MaskPixel>>,
+}
+impl DomainMask {
+    /// Load a mask from an image file, resampled to `grid_size` when given.
+    pub fn load(path: &Path, grid_size: Option<(usize, usize)>) -> Result<Self, String> {
+        let mut image = Image::load_image(&path.to_string_lossy())
+            .map_err(|e| format!("Unable to load mask {}: {}", path.display(), e))?;
+        if let Some((grid_size_x, grid_size_y)) = grid_size {
+            // Nearest neighbour keeps the colours of the palette intact
+            image.resize_nn(grid_size_x as i32, grid_size_y as i32);
+        }
+        if image.width() < 3 || image.height() < 3 {
+            return Err(format!("Invalid mask {}: image must be at least 3x3", path.display()));
+        }
+        Ok(Self::from_image(&image))
+    }
+
+    pub fn from_image(image: &Image) -> Self {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let colors = image.get_image_data();
+        let pixels = (0..width)
+            .map(|x_id| {
+                (0..height)
+                    .map(|y_id| MaskPixel::from_color(colors[y_id * width + x_id]))
+                    .collect()
+            })
+            .collect();
+        DomainMask { pixels }
+    }
+
+    pub fn grid_size(&self) -> (usize, usize) {
+        (self.pixels.len(), self.pixels[0].len())
+    }
+
+    /// Cells where the mask asks for dye to be injected
+    pub fn dye_source_cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+        for (x_id, column) in self.pixels.iter().enumerate() {
+            for (y_id, pixel) in column.iter().enumerate() {
+                if *pixel == MaskPixel::DyeSource {
+                    cells.push((x_id, y_id));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Copy every non fluid state of the mask into `fluid_domain`, which must have the mask size.
+    fn apply_to(&self, fluid_domain: &mut FluidDomain) {
+        for (x_id, column) in self.pixels.iter().enumerate() {
+            for (y_id, pixel) in column.iter().enumerate() {
+                let state = pixel.cell_state();
+                if state != CellState::Fluid {
+                    fluid_domain.set_cell_state(x_id, y_id, state);
+                }
+            }
+        }
+    }
+
+    pub fn build_domain(&self, timestep: f64) -> FluidDomain {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let mut fluid_domain = FluidDomain::new(grid_size_x, grid_size_y, timestep);
+        self.apply_to(&mut fluid_domain);
+        fluid_domain
+    }
+}
@@ -0,0 +1,17 @@
+//! Writers turning a `FluidDomain` into files read by external analysis tools.
+
+mod numpy;
+pub use numpy::write_npy_fields;
+mod vtk;
+pub use vtk::VtkSeries;
+
+use crate::fluid_engine::FluidCell;
+
+type CellValue = fn(&FluidCell) -> f64;
+
+/// Cell scalars written by every format, with their array names
+const CELL_SCALARS: [(&str, CellValue); 3] = [
+    ("pressure", |cell| cell.pressure),
+    ("divergence", |cell| cell.divergence),
+    ("dye", |cell| cell.dye),
+];
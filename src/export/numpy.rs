@@ -0,0 +1,55 @@
+use super::{CellValue, CELL_SCALARS};
+use crate::fluid_engine::*;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Write every cell quantity of `fluid_domain` as `.npy` arrays in `directory`:
+/// `u.npy`, `v.npy`, `pressure.npy`, `divergence.npy`, `dye.npy` (float64) and `state.npy` (uint8).
+///
+/// Arrays have shape `(grid_size_y, grid_size_x)` so `array[y_id, x_id]` is the cell seen in the
+/// window. Velocities are the raw staggered values (u on the left face, v on the top face).
+pub fn write_npy_fields(fluid_domain: &FluidDomain, directory: &Path) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+    let velocities: [(&str, CellValue); 2] = [("u", |cell| cell.velocity.0), ("v", |cell| cell.velocity.1)];
+    for (name, value_of) in velocities.into_iter().chain(CELL_SCALARS) {
+        let values: Vec<u8> = row_major_cells(fluid_domain)
+            .flat_map(|cell| value_of(cell).to_le_bytes())
+            .collect();
+        write_npy(&directory.join(format!("{}.npy", name)), "<f8", fluid_domain, &values)?;
+    }
+    let states: Vec<u8> = row_major_cells(fluid_domain)
+        .map(|cell| cell.state as u8)
+        .collect();
+    write_npy(&directory.join("state.npy"), "|u1", fluid_domain, &states)
+}
+
+/// Cells in C order for a `(grid_size_y, grid_size_x)` array
+fn row_major_cells(fluid_domain: &FluidDomain) -> impl Iterator<Item = &FluidCell> {
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    (0..grid_size_y).flat_map(move |y_id| {
+        (0..grid_size_x).map(move |x_id| &fluid_domain.fluid_grid[x_id][y_id])
+    })
+}
+
+/// NPY format version 1.0: magic, version, header length, then a dictionary padded so the
+/// data starts on a 64 bytes boundary.
+fn write_npy(path: &Path, descr: &str, fluid_domain: &FluidDomain, data: &[u8]) -> io::Result<()> {
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        descr, grid_size_y, grid_size_x
+    );
+    let preamble_len = 6 + 2 + 2;
+    let padding = 63 - (preamble_len + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(b"\x93NUMPY")?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()
+}
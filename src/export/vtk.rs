@@ -0,0 +1,144 @@
+use super::CELL_SCALARS;
+use crate::fluid_engine::*;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// VTK ImageData (`.vti`) frames plus the `.pvd` collection listing them, for ParaView.
+///
+/// The VTK y axis points up: rows are written bottom first and `v` is negated, so the
+/// view matches the window.
+pub struct VtkSeries {
+    directory: PathBuf,
+    name: String,
+    frames: Vec<(f64, String)>,
+}
+impl VtkSeries {
+    /// Frames are written to `directory` as `<name>_<step>.vti`, the collection as `<name>.pvd`.
+    pub fn new(directory: &Path, name: &str) -> Self {
+        VtkSeries {
+            directory: directory.to_path_buf(),
+            name: name.to_string(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Series continuing the collection already in `directory`, keeping its frames up to
+    /// `step`, for runs resumed from a snapshot
+    pub fn resume(directory: &Path, name: &str, step: usize) -> Self {
+        let mut series = Self::new(directory, name);
+        let collection = fs::read_to_string(directory.join(format!("{}.pvd", name))).unwrap_or_default();
+        for line in collection.lines() {
+            let attribute = |attribute_name: &str| {
+                let start = line.find(&format!("{}=\"", attribute_name))? + attribute_name.len() + 2;
+                line[start..].split('"').next()
+            };
+            let (Some(time), Some(file_name)) = (attribute("timestep"), attribute("file")) else {
+                continue;
+            };
+            let frame_step = file_name
+                .strip_prefix(&format!("{}_", name))
+                .and_then(|file_name| file_name.strip_suffix(".vti"))
+                .and_then(|frame_step| frame_step.parse::<usize>().ok());
+            if let (Ok(time), Some(frame_step)) = (time.parse(), frame_step) {
+                if frame_step <= step {
+                    series.frames.push((time, file_name.to_string()));
+                }
+            }
+        }
+        series
+    }
+
+    /// Write one frame and rewrite the collection, so it stays usable if the run stops early.
+    pub fn write_frame(&mut self, fluid_domain: &FluidDomain, step: usize, time: f64) -> io::Result<()> {
+        let file_name = format!("{}_{:06}.vti", self.name, step);
+        write_image_data(fluid_domain, &self.directory.join(&file_name))?;
+        self.frames.push((time, file_name));
+        self.write_collection()
+    }
+
+    fn write_collection(&self) -> io::Result<()> {
+        let path = self.directory.join(format!("{}.pvd", self.name));
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "<?xml version=\"1.0\"?>")?;
+        writeln!(
+            writer,
+            "<VTKFile type=\"Collection\" version=\"0.1\" byte_order=\"LittleEndian\">"
+        )?;
+        writeln!(writer, "  <Collection>")?;
+        for (time, file_name) in self.frames.iter() {
+            writeln!(
+                writer,
+                "    <DataSet timestep=\"{}\" group=\"\" part=\"0\" file=\"{}\"/>",
+                time, file_name
+            )?;
+        }
+        writeln!(writer, "  </Collection>")?;
+        writeln!(writer, "</VTKFile>")?;
+        writer.flush()
+    }
+}
+
+/// Single `.vti` file with one value per cell: velocity interpolated to the cell centre,
+/// pressure, divergence, dye and cell state.
+pub fn write_image_data(fluid_domain: &FluidDomain, path: &Path) -> io::Result<()> {
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    let spacing = fluid_domain.grid_spacing;
+    // VTK cell order: x first, from the bottom row to the top one
+    let cells = || {
+        (0..grid_size_y)
+            .rev()
+            .flat_map(move |y_id| (0..grid_size_x).map(move |x_id| (x_id, y_id)))
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "<?xml version=\"1.0\"?>")?;
+    writeln!(
+        writer,
+        "<VTKFile type=\"ImageData\" version=\"0.1\" byte_order=\"LittleEndian\">"
+    )?;
+    writeln!(
+        writer,
+        "  <ImageData WholeExtent=\"0 {} 0 {} 0 0\" Origin=\"0 0 0\" Spacing=\"{} {} {}\">",
+        grid_size_x, grid_size_y, spacing, spacing, spacing
+    )?;
+    writeln!(writer, "    <Piece Extent=\"0 {} 0 {} 0 0\">", grid_size_x, grid_size_y)?;
+    writeln!(writer, "      <CellData Scalars=\"pressure\" Vectors=\"velocity\">")?;
+
+    writeln!(
+        writer,
+        "        <DataArray type=\"Float64\" Name=\"velocity\" NumberOfComponents=\"3\" format=\"ascii\">"
+    )?;
+    for (x_id, y_id) in cells() {
+        let (u, v) = fluid_domain.cell_velocity(x_id, y_id);
+        writeln!(writer, "{} {} 0", u, -v)?;
+    }
+    writeln!(writer, "        </DataArray>")?;
+
+    for (name, value_of) in CELL_SCALARS {
+        writeln!(
+            writer,
+            "        <DataArray type=\"Float64\" Name=\"{}\" format=\"ascii\">",
+            name
+        )?;
+        for (x_id, y_id) in cells() {
+            writeln!(writer, "{}", value_of(&fluid_domain.fluid_grid[x_id][y_id]))?;
+        }
+        writeln!(writer, "        </DataArray>")?;
+    }
+
+    writeln!(
+        writer,
+        "        <DataArray type=\"UInt8\" Name=\"state\" format=\"ascii\">"
+    )?;
+    for (x_id, y_id) in cells() {
+        writeln!(writer, "{}", fluid_domain.fluid_grid[x_id][y_id].state as u8)?;
+    }
+    writeln!(writer, "        </DataArray>")?;
+
+    writeln!(writer, "      </CellData>")?;
+    writeln!(writer, "    </Piece>")?;
+    writeln!(writer, "  </ImageData>")?;
+    writeln!(writer, "</VTKFile>")?;
+    writer.flush()
+}
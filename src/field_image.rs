@@ -0,0 +1,170 @@
+use crate::colors::*;
+use crate::fluid_engine::*;
+use crate::scene_description::DisplayField;
+use raylib::prelude::*;
+use std::ffi::CStr;
+
+mod color_scale;
+mod lic;
+pub use color_scale::{format_value, ColorScale, RangeMode, LOG_DECADES};
+pub use lic::{draw_lic, LicColoring};
+
+/// One RGBA pixel per cell, row by row: the byte layout of an uncompressed raylib texture,
+/// so a frame is uploaded with a single `update_texture`
+pub struct PixelBuffer {
+    width: usize,
+    height: usize,
+    bytes: Vec<u8>,
+}
+impl PixelBuffer {
+    pub fn new(grid_size: (usize, usize)) -> Self {
+        PixelBuffer {
+            width: grid_size.0,
+            height: grid_size.1,
+            bytes: vec![255; 4 * grid_size.0 * grid_size.1],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set(&mut self, x_id: usize, y_id: usize, color: Color) {
+        let index = 4 * (y_id * self.width + x_id);
+        self.bytes[index..index + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn colors(&self) -> Vec<Color> {
+        self.bytes
+            .chunks_exact(4)
+            .map(|pixel| Color::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+            .collect()
+    }
+
+    /// Copy into a raylib image, for the image functions such as `export_image`
+    pub fn to_image(&self) -> Image {
+        let mut image = Image::gen_image_color(self.width as i32, self.height as i32, Color::new(0, 0, 0, 255));
+        for (pixel_id, color) in self.colors().into_iter().enumerate() {
+            image.draw_pixel((pixel_id % self.width) as i32, (pixel_id / self.width) as i32, color);
+        }
+        image
+    }
+}
+
+/// What a scene renders into its image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    Field(DisplayField),
+    Lic(LicColoring),
+}
+impl DisplayMode {
+    /// Field coloured through the colour scale, `None` when the mode has no colour scale
+    pub fn color_field(self) -> Option<DisplayField> {
+        match self {
+            DisplayMode::Field(field) => Some(field),
+            DisplayMode::Lic(coloring) => coloring.field(),
+        }
+    }
+}
+
+/// Entries of the display dropdown, in the order of `DISPLAY_MODE_LABELS`
+pub const DISPLAY_MODES: [DisplayMode; 12] = [
+    DisplayMode::Field(DisplayField::VelocityX),
+    DisplayMode::Field(DisplayField::VelocityY),
+    DisplayMode::Field(DisplayField::Pressure),
+    DisplayMode::Field(DisplayField::Dye),
+    DisplayMode::Field(DisplayField::Speed),
+    DisplayMode::Field(DisplayField::Vorticity),
+    DisplayMode::Field(DisplayField::Divergence),
+    DisplayMode::Field(DisplayField::QCriterion),
+    DisplayMode::Field(DisplayField::StreamFunction),
+    DisplayMode::Lic(LicColoring::Grey),
+    DisplayMode::Lic(LicColoring::Speed),
+    DisplayMode::Lic(LicColoring::Vorticity),
+];
+pub const DISPLAY_MODE_LABELS: &CStr = c"Velocity X;Velocity Y;Pressure;Dye;Speed;Vorticity;Divergence;Q-criterion;Stream function;LIC;LIC speed;LIC vorticity";
+
+/// Draw `mode`, fitting `color_scale` to the field shown
+pub fn draw_display_mode(pixels: &mut PixelBuffer, fluid_domain: &FluidDomain, mode: DisplayMode, color_scale: &mut ColorScale) {
+    match mode {
+        DisplayMode::Field(field) => draw_field(pixels, fluid_domain, field, color_scale),
+        DisplayMode::Lic(coloring) => draw_lic(pixels, fluid_domain, coloring, color_scale),
+    }
+}
+
+/// Value of `field` in every cell, indexed like `FluidDomain::fluid_grid`
+pub fn field_values(fluid_domain: &FluidDomain, field: DisplayField) -> Vec<Vec<f64>> {
+    if field == DisplayField::StreamFunction {
+        return fluid_domain.stream_function();
+    }
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    (0..grid_size_x)
+        .map(|x_id| {
+            (0..grid_size_y)
+                .map(|y_id| {
+                    let cell = &fluid_domain.fluid_grid[x_id][y_id];
+                    match field {
+                        DisplayField::VelocityX => cell.velocity.0,
+                        DisplayField::VelocityY => cell.velocity.1,
+                        DisplayField::Pressure => cell.pressure,
+                        DisplayField::Dye => cell.dye,
+                        DisplayField::Speed => fluid_domain.speed(x_id, y_id),
+                        DisplayField::Vorticity => fluid_domain.vorticity(x_id, y_id),
+                        DisplayField::Divergence => cell.divergence,
+                        DisplayField::QCriterion => fluid_domain.q_criterion(x_id, y_id),
+                        DisplayField::StreamFunction => unreachable!(),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Flat colour of the cells that do not show a field value, `None` for fluid cells
+pub fn cell_state_color(state: CellState) -> Option<Color> {
+    match state {
+        CellState::Wall => Some(Color::new(0, 0, 0, 255)),
+        CellState::Air => Some(COLOR_DARK),
+        CellState::Inflow => Some(COLOR_GREEN),
+        CellState::Outflow => Some(COLOR_RED),
+        CellState::Fluid => None,
+    }
+}
+
+/// Fit the range of `color_scale` to `values` over the fluid cells
+pub fn fit_to_fluid(color_scale: &mut ColorScale, fluid_domain: &FluidDomain, values: &[Vec<f64>]) {
+    let fluid_values = fluid_domain
+        .fluid_grid
+        .iter()
+        .flatten()
+        .zip(values.iter().flatten())
+        .filter(|(cell, _)| cell.state == CellState::Fluid)
+        .map(|(_, value)| *value);
+    color_scale.fit_range(fluid_values);
+}
+
+/// Draw one pixel per cell: `field` through `color_scale`, whose range is first fitted to the
+/// fluid cells, other cell states in flat colours.
+pub fn draw_field(pixels: &mut PixelBuffer, fluid_domain: &FluidDomain, field: DisplayField, color_scale: &mut ColorScale) {
+    draw_field_values(pixels, fluid_domain, &field_values(fluid_domain, field), color_scale);
+}
+
+/// `draw_field` for values already computed by `field_values`
+pub fn draw_field_values(pixels: &mut PixelBuffer, fluid_domain: &FluidDomain, values: &[Vec<f64>], color_scale: &mut ColorScale) {
+    fit_to_fluid(color_scale, fluid_domain, values);
+
+    for (x_id, column) in fluid_domain.fluid_grid.iter().enumerate() {
+        for (y_id, cell) in column.iter().enumerate() {
+            let color = cell_state_color(cell.state).unwrap_or_else(|| color_scale.color(values[x_id][y_id]));
+            pixels.set(x_id, y_id, color);
+        }
+    }
+}
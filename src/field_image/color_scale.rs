@@ -0,0 +1,176 @@
+use crate::colors::*;
+use crate::scene_description::DisplayField;
+use raylib::prelude::*;
+
+/// Decades shown below the top of the range in log scale
+pub const LOG_DECADES: f64 = 6.0;
+const LEGEND_WIDTH: i32 = 20;
+const LEGEND_HEIGHT: i32 = 200;
+const LEGEND_FONT_SIZE: i32 = 16;
+
+/// How the range of a field is chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeMode {
+    /// Lowest to highest fluid value of the current frame
+    Auto,
+    /// -m to m, m being the largest fluid magnitude of the current frame, so zero stays in the middle
+    Symmetric,
+    /// Range kept from frame to frame
+    Locked,
+}
+
+/// Maps field values to the colours of a colour map
+#[derive(Clone, Copy, Debug)]
+pub struct ColorScale {
+    pub colormap: Colormap,
+    pub range_mode: RangeMode,
+    /// Spread the values over `LOG_DECADES` decades below the top of the range.
+    /// Symmetric ranges apply it to the magnitudes on each side of zero.
+    pub log_scale: bool,
+    /// Values at level 0 and 1, those of the last drawn frame unless locked
+    pub range: (f64, f64),
+}
+impl ColorScale {
+    /// Diverging colour map and symmetric range for signed fields, viridis and automatic range otherwise
+    pub fn for_field(field: DisplayField) -> Self {
+        let signed = matches!(
+            field,
+            DisplayField::VelocityX
+                | DisplayField::VelocityY
+                | DisplayField::Vorticity
+                | DisplayField::Divergence
+                | DisplayField::QCriterion
+        );
+        ColorScale {
+            colormap: if signed { Colormap::Coolwarm } else { Colormap::Viridis },
+            range_mode: if signed { RangeMode::Symmetric } else { RangeMode::Auto },
+            log_scale: false,
+            range: (0.0, 1.0),
+        }
+    }
+
+    pub fn locked(colormap: Colormap, range: (f64, f64)) -> Self {
+        ColorScale {
+            colormap,
+            range_mode: RangeMode::Locked,
+            log_scale: false,
+            range,
+        }
+    }
+
+    /// Auto, then symmetric, then locked on the current range
+    pub fn cycle_range_mode(&mut self) {
+        self.range_mode = match self.range_mode {
+            RangeMode::Auto => RangeMode::Symmetric,
+            RangeMode::Symmetric => RangeMode::Locked,
+            RangeMode::Locked => RangeMode::Auto,
+        };
+    }
+
+    /// Fit the range to `values` according to the range mode
+    pub fn fit_range(&mut self, values: impl Iterator<Item = f64>) {
+        let (mut min_value, mut max_value, mut max_magnitude) = (f64::MAX, f64::MIN, 0.0f64);
+        for value in values.filter(|value| value.is_finite()) {
+            min_value = min_value.min(value);
+            max_value = max_value.max(value);
+            max_magnitude = max_magnitude.max(value.abs());
+        }
+        match self.range_mode {
+            RangeMode::Auto if min_value <= max_value => self.range = (min_value, max_value),
+            RangeMode::Auto => self.range = (0.0, 1.0),
+            RangeMode::Symmetric => self.range = (-max_magnitude, max_magnitude),
+            RangeMode::Locked => {}
+        }
+    }
+
+    /// Position of `value` along the colour map, in [0, 1]
+    pub fn level(&self, value: f64) -> f64 {
+        let (min_value, max_value) = self.range;
+        if max_value <= min_value {
+            return 0.5;
+        }
+        if !self.log_scale {
+            return ((value - min_value) / (max_value - min_value)).clamp(0.0, 1.0);
+        }
+        if self.range_mode == RangeMode::Symmetric {
+            let magnitude_level = log_level(value.abs(), max_value.abs().max(min_value.abs()));
+            return 0.5 + 0.5 * value.signum() * magnitude_level;
+        }
+        if max_value <= 0.0 {
+            return 0.0;
+        }
+        let bottom = min_value.max(max_value * 10f64.powf(-LOG_DECADES));
+        if value <= bottom {
+            return 0.0;
+        }
+        (value / bottom).log10() / (max_value / bottom).log10()
+    }
+
+    pub fn color(&self, value: f64) -> Color {
+        self.colormap.color(self.level(value))
+    }
+
+    /// Value drawn at `level`, inverse of `level`
+    pub fn value_at(&self, level: f64) -> f64 {
+        let (min_value, max_value) = self.range;
+        if !self.log_scale {
+            return min_value + (max_value - min_value) * level;
+        }
+        if self.range_mode == RangeMode::Symmetric {
+            let top = max_value.abs().max(min_value.abs());
+            let magnitude_level = (2.0 * level - 1.0).abs();
+            let magnitude = top * 10f64.powf(LOG_DECADES * (magnitude_level - 1.0));
+            return if level < 0.5 { -magnitude } else { magnitude };
+        }
+        let bottom = min_value.max(max_value * 10f64.powf(-LOG_DECADES));
+        bottom * (max_value / bottom).powf(level)
+    }
+
+    /// Colour bar in the bottom right corner of the screen, labelled with the top, middle and
+    /// bottom values of the range
+    pub fn draw_legend(&self, rl_handle: &mut RaylibDrawHandle) {
+        let x = rl_handle.get_screen_width() - LEGEND_WIDTH - 20;
+        let y = rl_handle.get_screen_height() - LEGEND_HEIGHT - 40;
+        for row in 0..LEGEND_HEIGHT {
+            let level = 1.0 - row as f64 / (LEGEND_HEIGHT - 1) as f64;
+            rl_handle.draw_rectangle(x, y + row, LEGEND_WIDTH, 1, self.colormap.color(level));
+        }
+        rl_handle.draw_rectangle_lines(x, y, LEGEND_WIDTH, LEGEND_HEIGHT, COLOR_LIGHT);
+
+        for (level, label_y) in [(1.0, y), (0.5, y + LEGEND_HEIGHT / 2), (0.0, y + LEGEND_HEIGHT)] {
+            let label = format_value(self.value_at(level));
+            let label_x = x - rl_handle.measure_text(&label, LEGEND_FONT_SIZE) - 8;
+            rl_handle.draw_text(&label, label_x, label_y - LEGEND_FONT_SIZE / 2, LEGEND_FONT_SIZE, COLOR_LIGHT);
+        }
+
+        let mut caption = self.colormap.name().to_string();
+        match self.range_mode {
+            RangeMode::Auto => {}
+            RangeMode::Symmetric => caption.push_str(", symmetric"),
+            RangeMode::Locked => caption.push_str(", locked"),
+        }
+        if self.log_scale {
+            caption.push_str(", log");
+        }
+        let caption_x = x + LEGEND_WIDTH - rl_handle.measure_text(&caption, LEGEND_FONT_SIZE);
+        rl_handle.draw_text(&caption, caption_x, y + LEGEND_HEIGHT + 12, LEGEND_FONT_SIZE, COLOR_LIGHT);
+    }
+}
+
+/// Level of a magnitude over the `LOG_DECADES` decades below `top`
+fn log_level(magnitude: f64, top: f64) -> f64 {
+    let bottom = top * 10f64.powf(-LOG_DECADES);
+    if magnitude <= bottom {
+        return 0.0;
+    }
+    ((magnitude / bottom).log10() / LOG_DECADES).min(1.0)
+}
+
+/// Short label of a field value
+pub fn format_value(value: f64) -> String {
+    if value != 0.0 && (value.abs() >= 1e4 || value.abs() < 1e-2) {
+        format!("{:.2e}", value)
+    } else {
+        format!("{:.3}", value)
+    }
+}
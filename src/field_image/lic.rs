@@ -0,0 +1,181 @@
+use super::{cell_state_color, field_values, fit_to_fluid, ColorScale, PixelBuffer};
+use crate::colors::*;
+use crate::fluid_engine::*;
+use crate::scene_description::DisplayField;
+use raylib::prelude::*;
+
+/// Streamline points averaged on each side of a cell
+const LIC_HALF_LENGTH: usize = 12;
+/// Distance between two streamline points, in cells
+const LIC_STEP: f64 = 0.5;
+
+/// Colour of a line integral convolution image, its brightness being the convolved noise
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LicColoring {
+    Grey,
+    /// Speed through the colour scale
+    Speed,
+    /// Vorticity through the colour scale
+    Vorticity,
+}
+impl LicColoring {
+    /// Field the colour scale applies to, `None` for the plain grey image
+    pub fn field(self) -> Option<DisplayField> {
+        match self {
+            LicColoring::Grey => None,
+            LicColoring::Speed => Some(DisplayField::Speed),
+            LicColoring::Vorticity => Some(DisplayField::Vorticity),
+        }
+    }
+}
+
+/// Draw one pixel per cell: white noise averaged along the streamline through each fluid cell,
+/// so the texture is stretched along the flow, tinted by the field of `coloring` through
+/// `color_scale` whose range is first fitted to the fluid cells. Other cell states are drawn as
+/// in `draw_field`.
+pub fn draw_lic(pixels: &mut PixelBuffer, fluid_domain: &FluidDomain, coloring: LicColoring, color_scale: &mut ColorScale) {
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    // Tracing one streamline per cell samples the velocity a lot: interpolate it between
+    // the cell centres of a flat copy rather than from the staggered faces every time
+    let velocity_field = CenteredVelocity::new(fluid_domain);
+
+    let mut intensities = vec![vec![0.0; grid_size_y]; grid_size_x];
+    let (mut min_intensity, mut max_intensity) = (f64::MAX, f64::MIN);
+    for (x_id, column) in intensities.iter_mut().enumerate() {
+        for (y_id, intensity) in column.iter_mut().enumerate() {
+            if fluid_domain.fluid_grid[x_id][y_id].state != CellState::Fluid {
+                continue;
+            }
+            *intensity = velocity_field.convolve_noise((x_id as f64 + 0.5, y_id as f64 + 0.5));
+            min_intensity = min_intensity.min(*intensity);
+            max_intensity = max_intensity.max(*intensity);
+        }
+    }
+    // Averaging flattens the noise, stretch it back to the full brightness range
+    let intensity_range = if max_intensity > min_intensity { max_intensity - min_intensity } else { 1.0 };
+
+    let color_values = coloring.field().map(|field| {
+        let values = field_values(fluid_domain, field);
+        fit_to_fluid(color_scale, fluid_domain, &values);
+        values
+    });
+
+    for (x_id, column) in fluid_domain.fluid_grid.iter().enumerate() {
+        for (y_id, cell) in column.iter().enumerate() {
+            let color = cell_state_color(cell.state).unwrap_or_else(|| {
+                let brightness = (intensities[x_id][y_id] - min_intensity) / intensity_range;
+                let base_color = match &color_values {
+                    Some(values) => color_scale.color(values[x_id][y_id]),
+                    None => COLOR_WHITE,
+                };
+                shade(base_color, brightness)
+            });
+            pixels.set(x_id, y_id, color);
+        }
+    }
+}
+
+/// Velocities at the cell centres, positions in cells
+struct CenteredVelocity {
+    grid_size: (usize, usize),
+    velocities: Vec<(f64, f64)>,
+    /// Fluid cells, where streamlines can go
+    open: Vec<bool>,
+}
+impl CenteredVelocity {
+    fn new(fluid_domain: &FluidDomain) -> Self {
+        let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+        let mut velocities = Vec::with_capacity(grid_size_x * grid_size_y);
+        let mut open = Vec::with_capacity(grid_size_x * grid_size_y);
+        for y_id in 0..grid_size_y {
+            for x_id in 0..grid_size_x {
+                velocities.push(fluid_domain.cell_velocity(x_id, y_id));
+                open.push(fluid_domain.fluid_grid[x_id][y_id].state == CellState::Fluid);
+            }
+        }
+        CenteredVelocity {
+            grid_size: (grid_size_x, grid_size_y),
+            velocities,
+            open,
+        }
+    }
+
+    fn cell_index(&self, position: (f64, f64)) -> Option<usize> {
+        let (grid_size_x, grid_size_y) = self.grid_size;
+        if position.0 < 0.0 || position.1 < 0.0 {
+            return None;
+        }
+        let (x_id, y_id) = (position.0 as usize, position.1 as usize);
+        (x_id < grid_size_x && y_id < grid_size_y).then_some(y_id * grid_size_x + x_id)
+    }
+
+    /// Unit flow direction, bilinearly interpolated between the cell centres
+    fn direction(&self, position: (f64, f64)) -> Option<(f64, f64)> {
+        let (grid_size_x, grid_size_y) = self.grid_size;
+        let x = (position.0 - 0.5).clamp(0.0, (grid_size_x - 1) as f64);
+        let y = (position.1 - 0.5).clamp(0.0, (grid_size_y - 1) as f64);
+        let x_id = (x as usize).min(grid_size_x - 2);
+        let y_id = (y as usize).min(grid_size_y - 2);
+        let (x_weight, y_weight) = (x - x_id as f64, y - y_id as f64);
+
+        let index = y_id * grid_size_x + x_id;
+        let corners = [
+            (index, (1.0 - x_weight) * (1.0 - y_weight)),
+            (index + 1, x_weight * (1.0 - y_weight)),
+            (index + grid_size_x, (1.0 - x_weight) * y_weight),
+            (index + grid_size_x + 1, x_weight * y_weight),
+        ];
+        let (mut u, mut v) = (0.0, 0.0);
+        for (corner_index, weight) in corners {
+            u += weight * self.velocities[corner_index].0;
+            v += weight * self.velocities[corner_index].1;
+        }
+        let speed = (u * u + v * v).sqrt();
+        (speed > STAGNATION_SPEED).then(|| (u / speed, v / speed))
+    }
+
+    /// Mean noise along the streamline through `seed`, traced both ways with midpoint steps
+    /// until it reaches a non fluid cell
+    fn convolve_noise(&self, seed: (f64, f64)) -> f64 {
+        let mut noise_sum = noise(seed.0 as usize, seed.1 as usize);
+        let mut sample_count = 1;
+        for step in [LIC_STEP, -LIC_STEP] {
+            let mut position = seed;
+            for _ in 0..LIC_HALF_LENGTH {
+                let Some(direction) = self.direction(position) else {
+                    break;
+                };
+                let midpoint = (
+                    position.0 + 0.5 * step * direction.0,
+                    position.1 + 0.5 * step * direction.1,
+                );
+                let Some(direction) = self.direction(midpoint) else {
+                    break;
+                };
+                position = (position.0 + step * direction.0, position.1 + step * direction.1);
+                match self.cell_index(position) {
+                    Some(index) if self.open[index] => {
+                        noise_sum += noise(position.0 as usize, position.1 as usize);
+                        sample_count += 1;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        noise_sum / sample_count as f64
+    }
+}
+
+/// Fixed white noise in [0, 1] attached to a cell
+fn noise(x_id: usize, y_id: usize) -> f64 {
+    let mut hash = (x_id as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (y_id as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    hash ^= hash >> 31;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 29;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn shade(color: Color, brightness: f64) -> Color {
+    let scale = |channel: u8| (channel as f64 * brightness).round() as u8;
+    Color::new(scale(color.r), scale(color.g), scale(color.b), 255)
+}
@@ -0,0 +1,262 @@
+use crate::contours::ContourOverlay;
+use crate::field_image::{draw_display_mode, draw_field_values, field_values, ColorScale, DisplayMode, PixelBuffer, DISPLAY_MODES, DISPLAY_MODE_LABELS};
+use crate::flow_lines::FlowLines;
+use crate::fluid_engine::{Diagnostics, FluidDomain};
+use crate::logging::RENDER;
+use crate::plots::PlotPanel;
+use crate::probes::ProbeSet;
+use crate::quiver::QuiverOverlay;
+use crate::scene_description::DisplayField;
+use crate::view_camera::ViewCamera;
+use raylib::prelude::*;
+
+mod field_shader;
+use field_shader::FieldShader;
+
+/// Isolines across the colour map, cycled with `I` (0 hides them)
+const ISOLINE_COUNTS: [usize; 3] = [0, 10, 20];
+
+/// Everything a scene shows of its `FluidDomain`: the selected display mode drawn through the
+/// view camera, with the contours, velocity arrows, flow lines and probes on top, and the plots
+/// of the global quantities.
+///
+/// Fields are coloured on the GPU by `FieldShader` when it loads, which also draws isolines, and
+/// on the CPU into a pixel buffer otherwise. The line integral convolution modes, recordings and
+/// `U` (GPU colouring off) use the pixel buffer.
+pub struct FieldRenderer {
+    pixels: PixelBuffer,
+    /// The pixel buffer no longer shows the last update, it is rendered again when asked for
+    pixels_stale: bool,
+    /// Displayed field of the last update, `None` in the LIC modes
+    values: Option<Vec<Vec<f64>>>,
+    texture: Texture2D,
+    field_shader: Option<FieldShader>,
+    use_field_shader: bool,
+    /// The last update prepared the field shader rather than the pixel buffer
+    shader_frame: bool,
+    isoline_count_index: usize,
+    display: DisplaySettings,
+    contours: ContourOverlay,
+    quiver: QuiverOverlay,
+    flow_lines: FlowLines,
+    probes: ProbeSet,
+    plots: PlotPanel,
+    /// Those of the last step, `None` before the first one
+    diagnostics: Option<Diagnostics>,
+}
+impl FieldRenderer {
+    /// `export_prefix` names the flow line, contour and probe CSV files
+    pub fn new(
+        rl_handle: &mut RaylibHandle,
+        rl_thread: &RaylibThread,
+        grid_size: (usize, usize),
+        display: DisplaySettings,
+        export_prefix: &str,
+    ) -> Result<Self, String> {
+        let image = Image::gen_image_color(grid_size.0 as i32, grid_size.1 as i32, Color::new(0, 0, 0, 255));
+        let field_shader = match FieldShader::new(rl_handle, rl_thread, grid_size) {
+            Ok(field_shader) => Some(field_shader),
+            Err(error) => {
+                log::warn!(target: RENDER, "Fields are coloured on the CPU: {}", error);
+                None
+            }
+        };
+        Ok(FieldRenderer {
+            pixels: PixelBuffer::new(grid_size),
+            pixels_stale: true,
+            values: None,
+            texture: rl_handle
+                .load_texture_from_image(rl_thread, &image)
+                .map_err(|e| e.to_string())?,
+            field_shader,
+            use_field_shader: true,
+            shader_frame: false,
+            isoline_count_index: 0,
+            display,
+            contours: ContourOverlay::new(export_prefix),
+            quiver: QuiverOverlay::new(),
+            flow_lines: FlowLines::new(export_prefix),
+            probes: ProbeSet::new(export_prefix),
+            plots: PlotPanel::new(),
+            diagnostics: None,
+        })
+    }
+
+    /// Keys of the overlays every scene has, for the scene help pages
+    pub fn help_text() -> Vec<&'static str> {
+        let mut help_text = QuiverOverlay::help_text();
+        help_text.extend(FlowLines::help_text());
+        help_text
+    }
+
+    /// Last update of `fluid_domain`, one pixel per cell
+    pub fn pixels(&mut self, fluid_domain: &FluidDomain) -> &PixelBuffer {
+        if self.pixels_stale {
+            match &self.values {
+                Some(values) => draw_field_values(&mut self.pixels, fluid_domain, values, &mut self.display.color_scale),
+                None => self.display.draw_pixels(&mut self.pixels, fluid_domain),
+            }
+            self.pixels_stale = false;
+        }
+        &self.pixels
+    }
+
+    /// Labelled contours of the displayed field at `levels`
+    pub fn set_contour_levels(&mut self, levels: Vec<f64>) {
+        self.contours.set_levels(levels);
+    }
+
+    pub fn texture(&self) -> &Texture2D {
+        &self.texture
+    }
+
+    /// Move the pathlines along, log the probes, compute the diagnostics and record the plotted
+    /// quantities, after every simulation step, `step` being the number of steps done
+    pub fn advance(&mut self, fluid_domain: &FluidDomain, step: usize) {
+        self.flow_lines.advance(fluid_domain);
+        self.probes.record(fluid_domain, step);
+        let diagnostics = self.diagnostics.insert(fluid_domain.diagnostics());
+        self.plots.record(diagnostics, &self.probes.point_speeds(fluid_domain));
+    }
+
+    /// Diagnostics of the last step, `None` before the first one
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    /// Handle the display and overlay keys, then render `fluid_domain`.
+    /// `I` cycles the isolines and `U` switches between GPU and CPU colouring.
+    pub fn update(&mut self, rl_handle: &RaylibHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_I) {
+            self.isoline_count_index = (self.isoline_count_index + 1) % ISOLINE_COUNTS.len();
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_U) {
+            self.use_field_shader = !self.use_field_shader;
+        }
+        self.display.update(rl_handle);
+        self.contours.update(rl_handle);
+        self.quiver.update(rl_handle);
+        self.plots.update(rl_handle);
+        self.flow_lines.update(rl_handle, fluid_domain, view);
+        self.probes.update(rl_handle, fluid_domain, view);
+
+        // Computed once for the colouring and the contours, the stream function being a
+        // Poisson solve
+        let values = match self.display.mode() {
+            DisplayMode::Field(field) => Some(field_values(fluid_domain, field)),
+            DisplayMode::Lic(_) => None,
+        };
+        self.shader_frame = false;
+        match (&values, self.field_shader.as_mut()) {
+            (Some(values), Some(field_shader)) if self.use_field_shader => {
+                field_shader.prepare(fluid_domain, values, &mut self.display.color_scale);
+                self.shader_frame = true;
+                self.pixels_stale = true;
+            }
+            (Some(values), _) => {
+                draw_field_values(&mut self.pixels, fluid_domain, values, &mut self.display.color_scale);
+                self.pixels_stale = false;
+            }
+            (None, _) => {
+                self.display.draw_pixels(&mut self.pixels, fluid_domain);
+                self.pixels_stale = false;
+            }
+        }
+        self.contours.compute(fluid_domain, values.as_deref(), &self.display.color_scale);
+        // Kept for `pixels`, which renders shader frames again on the CPU for recordings
+        self.values = values;
+    }
+
+    /// Draw the last update with the overlays
+    pub fn draw(&mut self, rl_handle: &mut RaylibDrawHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        match self.field_shader.as_mut() {
+            Some(field_shader) if self.shader_frame => {
+                let isoline_count = ISOLINE_COUNTS[self.isoline_count_index];
+                let contour_spacing = if isoline_count == 0 { 0.0 } else { 1.0 / isoline_count as f32 };
+                field_shader.draw(rl_handle, view, &self.display.color_scale, contour_spacing);
+            }
+            _ => {
+                self.texture.update_texture(self.pixels.bytes());
+                view.draw_texture(rl_handle, &self.texture);
+            }
+        }
+        self.contours.draw(rl_handle, fluid_domain, view);
+        self.flow_lines.draw(rl_handle, fluid_domain, view);
+        self.quiver.draw(rl_handle, fluid_domain, view);
+        self.probes.draw(rl_handle, fluid_domain, view);
+    }
+
+    /// Plots, colour bar and display dropdown, drawn last to stay above everything else
+    pub fn draw_controls(&mut self, rl_handle: &mut RaylibDrawHandle) {
+        self.plots.draw(rl_handle);
+        self.display.draw(rl_handle);
+    }
+}
+
+/// Display dropdown and colour scale of a scene. C cycles the colour maps, O the range modes
+/// (automatic, symmetric around zero, locked on the current range) and G toggles the log scale.
+pub struct DisplaySettings {
+    selected: i32,
+    edit_mode: bool,
+    color_scale: ColorScale,
+    /// Mode `color_scale` was set up for, the scale goes back to the field defaults when it changes
+    scaled_mode: DisplayMode,
+}
+impl DisplaySettings {
+    pub fn new(mode: DisplayMode) -> Self {
+        let field = mode.color_field().unwrap_or(DisplayField::Speed);
+        Self::with_color_scale(mode, ColorScale::for_field(field))
+    }
+
+    pub fn with_color_scale(mode: DisplayMode, color_scale: ColorScale) -> Self {
+        DisplaySettings {
+            selected: DISPLAY_MODES.iter().position(|entry| *entry == mode).unwrap_or(0) as i32,
+            edit_mode: false,
+            color_scale,
+            scaled_mode: mode,
+        }
+    }
+
+    pub fn mode(&self) -> DisplayMode {
+        DISPLAY_MODES[(self.selected.max(0) as usize).min(DISPLAY_MODES.len() - 1)]
+    }
+
+    pub fn update(&mut self, rl_handle: &RaylibHandle) {
+        let mode = self.mode();
+        if mode != self.scaled_mode {
+            if let Some(field) = mode.color_field() {
+                self.color_scale = ColorScale::for_field(field);
+            }
+            self.scaled_mode = mode;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_C) {
+            self.color_scale.colormap = self.color_scale.colormap.next();
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_O) {
+            self.color_scale.cycle_range_mode();
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_G) {
+            self.color_scale.log_scale = !self.color_scale.log_scale;
+        }
+    }
+
+    /// Render the selected mode of `fluid_domain` into `pixels`
+    pub fn draw_pixels(&mut self, pixels: &mut PixelBuffer, fluid_domain: &FluidDomain) {
+        draw_display_mode(pixels, fluid_domain, self.mode(), &mut self.color_scale);
+    }
+
+    /// Colour bar of the displayed field and dropdown
+    pub fn draw(&mut self, rl_handle: &mut RaylibDrawHandle) {
+        if self.mode().color_field().is_some() {
+            self.color_scale.draw_legend(rl_handle);
+        }
+        if rl_handle.gui_dropdown_box(
+            Rectangle::new(10.0, 10.0, 170.0, 30.0),
+            Some(DISPLAY_MODE_LABELS),
+            &mut self.selected,
+            self.edit_mode,
+        ) {
+            self.edit_mode = !self.edit_mode;
+        }
+    }
+}
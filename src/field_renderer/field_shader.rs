@@ -0,0 +1,156 @@
+use crate::colors::*;
+use crate::field_image::{cell_state_color, fit_to_fluid, ColorScale, PixelBuffer, RangeMode, LOG_DECADES};
+use crate::fluid_engine::FluidDomain;
+use crate::view_camera::ViewCamera;
+use raylib::consts::PixelFormat;
+use raylib::ffi;
+use raylib::prelude::*;
+
+const FIELD_SHADER_SOURCE: &str = include_str!("../../assets/shaders/field.fs");
+/// WebGL runs GLSL 100 shaders, desktop OpenGL 3.3 ones
+#[cfg(target_arch = "wasm32")]
+const GLSL_HEADER: &str = "#version 100
+#ifdef GL_FRAGMENT_PRECISION_HIGH
+precision highp float;
+#else
+precision mediump float;
+#endif
+#define VARYING varying
+#define finalColor gl_FragColor
+";
+#[cfg(not(target_arch = "wasm32"))]
+const GLSL_HEADER: &str = "#version 330
+#define VARYING in
+#define texture2D texture
+out vec4 finalColor;
+";
+const PALETTE_SIZE: usize = 256;
+
+struct UniformLocations {
+    values: i32,
+    palette: i32,
+    grid_size: i32,
+    range: i32,
+    log_scale: i32,
+    symmetric: i32,
+    log_decades: i32,
+    smoothing: i32,
+    contour_spacing: i32,
+    pixels_per_cell: i32,
+}
+
+/// Colours scalar fields on the GPU: the values go to a single channel float texture and
+/// `assets/shaders/field.fs` maps them through a palette texture, interpolates between the cell
+/// centres, masks the other cell states and draws isolines.
+pub struct FieldShader {
+    shader: Shader,
+    locations: UniformLocations,
+    grid_size: (usize, usize),
+    /// One `f32` per cell, row by row
+    value_bytes: Vec<u8>,
+    values: Texture2D,
+    /// Flat colour of the cells that are not fluid, transparent on fluid cells
+    mask_pixels: PixelBuffer,
+    mask: Texture2D,
+    palette: Texture2D,
+    palette_colormap: Option<Colormap>,
+}
+impl FieldShader {
+    /// Fails when the shader does not compile or float textures are not supported
+    pub fn new(rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread, grid_size: (usize, usize)) -> Result<Self, String> {
+        let source = format!("{}{}", GLSL_HEADER, FIELD_SHADER_SOURCE);
+        let shader = rl_handle.load_shader_from_memory(rl_thread, None, Some(&source));
+        // A shader that fails to compile is replaced by the default one, which has none of the uniforms
+        let location = |name: &str| match shader.get_shader_location(name) {
+            -1 => Err(format!("Field shader has no {} uniform", name)),
+            location => Ok(location),
+        };
+        let locations = UniformLocations {
+            values: location("values")?,
+            palette: location("palette")?,
+            grid_size: location("gridSize")?,
+            range: location("range")?,
+            log_scale: location("logScale")?,
+            symmetric: location("symmetric")?,
+            log_decades: location("logDecades")?,
+            smoothing: location("smoothing")?,
+            contour_spacing: location("contourSpacing")?,
+            pixels_per_cell: location("pixelsPerCell")?,
+        };
+
+        let (width, height) = (grid_size.0 as i32, grid_size.1 as i32);
+        let mut values_image = Image::gen_image_color(width, height, COLOR_BLACK);
+        values_image.set_format(PixelFormat::PIXELFORMAT_UNCOMPRESSED_R32);
+        let values = rl_handle.load_texture_from_image(rl_thread, &values_image)?;
+        let mask = rl_handle.load_texture_from_image(rl_thread, &Image::gen_image_color(width, height, COLOR_BLACK))?;
+        let palette =
+            rl_handle.load_texture_from_image(rl_thread, &Image::gen_image_color(PALETTE_SIZE as i32, 1, COLOR_BLACK))?;
+
+        Ok(FieldShader {
+            shader,
+            locations,
+            grid_size,
+            value_bytes: Vec::with_capacity(4 * grid_size.0 * grid_size.1),
+            values,
+            mask_pixels: PixelBuffer::new(grid_size),
+            mask,
+            palette,
+            palette_colormap: None,
+        })
+    }
+
+    /// Take the field `values` (see `field_values`) and fit `color_scale` to them, the textures
+    /// are uploaded by `draw`
+    pub fn prepare(&mut self, fluid_domain: &FluidDomain, values: &[Vec<f64>], color_scale: &mut ColorScale) {
+        fit_to_fluid(color_scale, fluid_domain, values);
+
+        self.value_bytes.clear();
+        for y_id in 0..self.grid_size.1 {
+            for (x_id, column) in values.iter().enumerate() {
+                self.value_bytes.extend_from_slice(&(column[y_id] as f32).to_ne_bytes());
+                let state = fluid_domain.fluid_grid[x_id][y_id].state;
+                self.mask_pixels
+                    .set(x_id, y_id, cell_state_color(state).unwrap_or(Color::new(0, 0, 0, 0)));
+            }
+        }
+    }
+
+    /// Draw the prepared field through `view`, with an isoline every `contour_spacing` levels
+    /// (none when 0)
+    pub fn draw(&mut self, rl_handle: &mut RaylibDrawHandle, view: &ViewCamera, color_scale: &ColorScale, contour_spacing: f32) {
+        self.values.update_texture(&self.value_bytes);
+        self.mask.update_texture(self.mask_pixels.bytes());
+        if self.palette_colormap != Some(color_scale.colormap) {
+            let palette_bytes: Vec<u8> = (0..PALETTE_SIZE)
+                .flat_map(|index| {
+                    let color = color_scale.colormap.color(index as f64 / (PALETTE_SIZE - 1) as f64);
+                    [color.r, color.g, color.b, 255]
+                })
+                .collect();
+            self.palette.update_texture(&palette_bytes);
+            self.palette_colormap = Some(color_scale.colormap);
+        }
+
+        let locations = &self.locations;
+        let grid_size = Vector2::new(self.grid_size.0 as f32, self.grid_size.1 as f32);
+        let range = Vector2::new(color_scale.range.0 as f32, color_scale.range.1 as f32);
+        self.shader.set_shader_value(locations.grid_size, grid_size);
+        self.shader.set_shader_value(locations.range, range);
+        self.shader.set_shader_value(locations.log_scale, color_scale.log_scale as i32);
+        self.shader
+            .set_shader_value(locations.symmetric, (color_scale.range_mode == RangeMode::Symmetric) as i32);
+        self.shader.set_shader_value(locations.log_decades, LOG_DECADES as f32);
+        self.shader.set_shader_value(locations.smoothing, view.bilinear_filtering() as i32);
+        self.shader.set_shader_value(locations.contour_spacing, contour_spacing);
+        self.shader.set_shader_value(locations.pixels_per_cell, view.zoom());
+
+        // Sampler uniforms only hold until the next batch is drawn, which beginning the shader
+        // and 2D modes both do, so they are set right before drawing
+        let raw_shader: ffi::Shader = *self.shader.as_ref();
+        let mut rl_shader_handle = rl_handle.begin_shader_mode(&raw_shader);
+        let mut rl_mode_handle = rl_shader_handle.begin_mode2D(view.camera());
+        self.shader.set_shader_value_texture(locations.values, &self.values);
+        self.shader.set_shader_value_texture(locations.palette, &self.palette);
+        rl_mode_handle.draw_texture(&self.mask, 0, 0, COLOR_WHITE);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::colors::*;
+use crate::fluid_engine::*;
+use crate::logging::EXPORT;
+use crate::recording::next_free_path;
+use crate::view_camera::ViewCamera;
+use raylib::prelude::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Distance between two evenly spaced seeds, in cells
+const SEED_SPACING: usize = 16;
+/// Streamline integration step, in cells
+const STREAMLINE_STEP: f64 = 0.5;
+const STREAMLINE_MAX_POINTS: usize = 400;
+/// Simulation steps between two tracer releases from every seed
+const RELEASE_INTERVAL: usize = 10;
+const PATHLINE_MAX_POINTS: usize = 300;
+const MAX_PATHLINES: usize = 200;
+
+struct Pathline {
+    /// Positions of the tracer at every step since its release (metres)
+    points: Vec<(f64, f64)>,
+    /// False once the tracer hit a wall or left the grid
+    moving: bool,
+}
+
+/// Streamlines of the current velocity field and pathlines of tracers released over time,
+/// both drawn as polylines over the scene.
+///
+/// Lines start from the seeds placed with `K` or, when there are none, from seeds evenly
+/// spaced over the fluid cells.
+pub struct FlowLines {
+    pub show_streamlines: bool,
+    pub show_pathlines: bool,
+    /// Seeds placed by the user (metres)
+    user_seeds: Vec<(f64, f64)>,
+    streamlines: Vec<Vec<(f64, f64)>>,
+    pathlines: VecDeque<Pathline>,
+    steps_since_release: usize,
+    /// Exports go to `<export_prefix>_lines_<index>.csv`
+    export_prefix: String,
+}
+impl FlowLines {
+    pub fn new(export_prefix: &str) -> Self {
+        FlowLines {
+            show_streamlines: false,
+            show_pathlines: false,
+            user_seeds: Vec::new(),
+            streamlines: Vec::new(),
+            pathlines: VecDeque::new(),
+            steps_since_release: 0,
+            export_prefix: export_prefix.to_string(),
+        }
+    }
+
+    pub fn help_text() -> Vec<&'static str> {
+        vec![
+            "T: toggle streamlines, P: toggle pathlines",
+            "K: add a line seed under the mouse, Shift + K: back to evenly spaced seeds",
+            "X: export the streamlines and pathlines as CSV point lists",
+        ]
+    }
+
+    /// `T` toggles streamlines, `P` pathlines, `K` places a seed under the mouse
+    /// (`Shift + K` removes them all) and `X` exports the lines. Streamlines are traced
+    /// again from the current velocity field.
+    pub fn update(&mut self, rl_handle: &RaylibHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_T) {
+            self.show_streamlines = !self.show_streamlines;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_P) {
+            self.show_pathlines = !self.show_pathlines;
+            self.pathlines.clear();
+            self.steps_since_release = 0;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_K) {
+            if rl_handle.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
+                self.user_seeds.clear();
+            } else {
+                let (grid_x, grid_y) = view.screen_to_grid(rl_handle.get_mouse_position());
+                let seed = (grid_x * fluid_domain.grid_spacing, grid_y * fluid_domain.grid_spacing);
+                if fluid_domain.is_open_position(seed) {
+                    self.user_seeds.push(seed);
+                }
+            }
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_X) {
+            let path = next_free_path(&format!("{}_lines", self.export_prefix), ".csv");
+            match self.export_csv(&path) {
+                Ok(()) => log::info!(target: EXPORT, "Flow lines written to {}", path.display()),
+                Err(error) => log::error!(target: EXPORT, "Unable to write {}: {}", path.display(), error),
+            }
+        }
+
+        self.streamlines.clear();
+        if self.show_streamlines {
+            let step_length = STREAMLINE_STEP * fluid_domain.grid_spacing;
+            self.streamlines = self
+                .seeds(fluid_domain)
+                .into_iter()
+                .map(|seed| fluid_domain.trace_streamline(seed, step_length, STREAMLINE_MAX_POINTS))
+                .filter(|streamline| streamline.len() > 1)
+                .collect();
+        }
+    }
+
+    /// Move the pathline tracers along one simulation step, releasing new ones from the seeds
+    /// every `RELEASE_INTERVAL` steps. To be called after every step of `fluid_domain`.
+    pub fn advance(&mut self, fluid_domain: &FluidDomain) {
+        if !self.show_pathlines {
+            return;
+        }
+        for pathline in self.pathlines.iter_mut().filter(|pathline| pathline.moving) {
+            let position = *pathline.points.last().unwrap();
+            let next_position = fluid_domain.advect_point(position, fluid_domain.timestep);
+            let open = fluid_domain.is_open_position(next_position);
+            if open {
+                pathline.points.push(next_position);
+            }
+            pathline.moving = open && pathline.points.len() < PATHLINE_MAX_POINTS;
+        }
+
+        if self.steps_since_release == 0 {
+            for seed in self.seeds(fluid_domain) {
+                self.pathlines.push_back(Pathline {
+                    points: vec![seed],
+                    moving: true,
+                });
+            }
+            while self.pathlines.len() > MAX_PATHLINES {
+                self.pathlines.pop_front();
+            }
+        }
+        self.steps_since_release = (self.steps_since_release + 1) % RELEASE_INTERVAL;
+    }
+
+    pub fn draw(&self, rl_handle: &mut RaylibDrawHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        let to_screen = |point: &(f64, f64)| {
+            view.grid_to_screen((point.0 / fluid_domain.grid_spacing, point.1 / fluid_domain.grid_spacing))
+        };
+        for streamline in self.streamlines.iter() {
+            draw_polyline(rl_handle, streamline.iter().map(to_screen), COLOR_WHITE);
+        }
+        if self.show_pathlines {
+            for pathline in self.pathlines.iter() {
+                draw_polyline(rl_handle, pathline.points.iter().map(to_screen), COLOR_DARK);
+            }
+        }
+        if self.show_streamlines || self.show_pathlines {
+            for seed in self.user_seeds.iter() {
+                rl_handle.draw_circle_v(to_screen(seed), 3.0, COLOR_YELLOW);
+            }
+        }
+    }
+
+    /// Write every displayed line as a point list, positions in metres.
+    /// Columns: kind (streamline or pathline), line, point, x, y
+    pub fn export_csv(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "kind,line,point,x,y")?;
+        let pathlines = self.pathlines.iter().map(|pathline| &pathline.points);
+        for (kind, lines) in [
+            ("streamline", self.streamlines.iter().collect::<Vec<_>>()),
+            ("pathline", pathlines.collect()),
+        ] {
+            for (line_id, line) in lines.iter().enumerate() {
+                for (point_id, (x, y)) in line.iter().enumerate() {
+                    writeln!(writer, "{},{},{},{},{}", kind, line_id, point_id, x, y)?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    fn seeds(&self, fluid_domain: &FluidDomain) -> Vec<(f64, f64)> {
+        if !self.user_seeds.is_empty() {
+            return self.user_seeds.clone();
+        }
+        let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+        let mut seeds = Vec::new();
+        for x_id in (SEED_SPACING / 2..grid_size_x).step_by(SEED_SPACING) {
+            for y_id in (SEED_SPACING / 2..grid_size_y).step_by(SEED_SPACING) {
+                let seed = (
+                    (x_id as f64 + 0.5) * fluid_domain.grid_spacing,
+                    (y_id as f64 + 0.5) * fluid_domain.grid_spacing,
+                );
+                if fluid_domain.is_open_position(seed) {
+                    seeds.push(seed);
+                }
+            }
+        }
+        seeds
+    }
+}
+
+fn draw_polyline(rl_handle: &mut RaylibDrawHandle, points: impl Iterator<Item = Vector2>, color: Color) {
+    let mut previous_point: Option<Vector2> = None;
+    for point in points {
+        if let Some(previous_point) = previous_point {
+            rl_handle.draw_line_v(previous_point, point, color);
+        }
+        previous_point = Some(point);
+    }
+}
@@ -1,15 +1,55 @@
-#[derive(Clone, Copy, PartialEq, Eq)]
+mod liquid;
+pub use liquid::LiquidParticles;
+mod snapshot;
+pub use snapshot::SnapshotHeader;
+mod tracing;
+pub use tracing::STAGNATION_SPEED;
+mod derived;
+mod diagnostics;
+pub use diagnostics::{Diagnostics, ProjectionStats};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CellState {
     Wall = 0,
     Fluid = 1,
+    /// Empty cell above a free surface (liquid simulation only)
+    Air = 2,
+    /// Solid boundary cell pushing fluid at `FluidDomain::inflow_velocity`
+    Inflow = 3,
+    /// Open boundary cell at zero pressure, fluid leaves the domain freely
+    Outflow = 4,
+}
+impl CellState {
+    /// Inverse of `state as u8`
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(CellState::Wall),
+            1 => Some(CellState::Fluid),
+            2 => Some(CellState::Air),
+            3 => Some(CellState::Inflow),
+            4 => Some(CellState::Outflow),
+            _ => None,
+        }
+    }
+
+    /// 0.0 if the cell blocks the flow, 1.0 if its faces can carry velocity.
+    pub fn flow_factor(self) -> f64 {
+        match self {
+            CellState::Wall | CellState::Inflow => 0.0,
+            CellState::Fluid | CellState::Air | CellState::Outflow => 1.0,
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
 pub struct FluidCell {
     pub velocity: (f64, f64),
+    /// Divergence left by the last pressure projection (1/s), 0 in the cells it does not project
     pub divergence: f64,
     pub state: CellState,
     pub pressure: f64,
+    /// Passive scalar carried by the flow (0.0 clear, 1.0 fully dyed)
+    pub dye: f64,
 }
 impl Default for FluidCell {
     fn default() -> Self {
@@ -18,25 +58,43 @@ impl Default for FluidCell {
             divergence: 0f64,
             state: CellState::Fluid,
             pressure: 0f64,
+            dye: 0f64,
         }
     }
 }
 
-const GRID_SPACING: f64 = 1.0;
+pub const DEFAULT_GRID_SPACING: f64 = 1.0;
+/// Grid sizes accepted from scene files and snapshots, per axis
+pub const MIN_GRID_SIZE: usize = 3;
+pub const MAX_GRID_SIZE: usize = 2048;
 const FLUID_DENSITY: f64 = 1000f64; // Water 1000 kg/m³
+const PRESSURE_ITERATIONS: usize = 40;
 
-pub struct FluidDomain<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> {
-    pub fluid_grid: [[FluidCell; GRID_SIZE_Y]; GRID_SIZE_X],
+pub struct FluidDomain {
+    pub fluid_grid: Vec<Vec<FluidCell>>,
     pub timestep : f64,
+    /// Size of a cell side in metres
+    pub grid_spacing: f64,
+    /// Velocity imposed by `CellState::Inflow` cells
+    pub inflow_velocity: (f64, f64),
+    pub projection: ProjectionStats,
 }
-impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X, GRID_SIZE_Y> {
-    pub fn new(timestep: f64) -> Self {
+impl FluidDomain {
+    pub fn new(grid_size_x: usize, grid_size_y: usize, timestep: f64) -> Self {
         FluidDomain {
-            fluid_grid: [[FluidCell::default(); GRID_SIZE_Y]; GRID_SIZE_X],
+            fluid_grid: vec![vec![FluidCell::default(); grid_size_y]; grid_size_x],
             timestep,
+            grid_spacing: DEFAULT_GRID_SPACING,
+            inflow_velocity: (0.0, 0.0),
+            projection: ProjectionStats::default(),
         }
     }
 
+    /// Number of cells along x and y
+    pub fn grid_size(&self) -> (usize, usize) {
+        (self.fluid_grid.len(), self.fluid_grid[0].len())
+    }
+
     pub fn set_cell_state(&mut self, x_id: usize, y_id: usize, new_state: CellState) {
         if self.fluid_grid[x_id][y_id].state == new_state {
             return;
@@ -45,18 +103,65 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
         if self.fluid_grid[x_id][y_id].state == CellState::Fluid {
             self.fluid_grid[x_id][y_id].velocity.0 = 0.0;
             self.fluid_grid[x_id][y_id].velocity.1 = 0.0;
-            if y_id + 1 < GRID_SIZE_Y {
+            if y_id + 1 < self.fluid_grid[0].len() {
                 self.fluid_grid[x_id][y_id + 1].velocity.1 = 0.0;
             }
-            if x_id + 1 < GRID_SIZE_X {
+            if x_id + 1 < self.fluid_grid.len() {
                 self.fluid_grid[x_id + 1][y_id].velocity.0 = 0.0;
             }
         }
         self.fluid_grid[x_id][y_id].state = new_state;
     }
 
+    /// Velocity at the centre of a cell, averaged from its staggered faces
+    pub fn cell_velocity(&self, x_id: usize, y_id: usize) -> (f64, f64) {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let u_right = if x_id + 1 < grid_size_x {
+            self.fluid_grid[x_id + 1][y_id].velocity.0
+        } else {
+            self.fluid_grid[x_id][y_id].velocity.0
+        };
+        let v_bottom = if y_id + 1 < grid_size_y {
+            self.fluid_grid[x_id][y_id + 1].velocity.1
+        } else {
+            self.fluid_grid[x_id][y_id].velocity.1
+        };
+        (
+            (self.fluid_grid[x_id][y_id].velocity.0 + u_right) / 2.0,
+            (self.fluid_grid[x_id][y_id].velocity.1 + v_bottom) / 2.0,
+        )
+    }
+
+    /// Net outflow of a fluid cell with its current velocities (0 if the field is divergence free)
+    pub fn velocity_divergence(&self, x_id: usize, y_id: usize) -> f64 {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        if x_id + 1 >= grid_size_x || y_id + 1 >= grid_size_y {
+            return 0.0;
+        }
+        (self.fluid_grid[x_id + 1][y_id].velocity.0 - self.fluid_grid[x_id][y_id].velocity.0
+            + self.fluid_grid[x_id][y_id + 1].velocity.1
+            - self.fluid_grid[x_id][y_id].velocity.1)
+            / self.grid_spacing
+    }
+
+    /// Total kinetic energy of the fluid cells (J per metre of depth)
+    pub fn kinetic_energy(&self) -> f64 {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let mut kinetic_energy = 0.0;
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                if self.fluid_grid[x_id][y_id].state != CellState::Fluid {
+                    continue;
+                }
+                let (u, v) = self.cell_velocity(x_id, y_id);
+                kinetic_energy += 0.5 * FLUID_DENSITY * (u * u + v * v) * self.grid_spacing * self.grid_spacing;
+            }
+        }
+        kinetic_energy
+    }
+
     pub fn sample_grid_velocity_u(&self, x: f64, y: f64) -> f64 {
-        let mut x_id = (x / GRID_SPACING).floor() as i64;
+        let mut x_id = (x / self.grid_spacing).floor() as i64;
         if x_id < 0 {
             x_id = 0;
         }
@@ -65,7 +170,7 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
             x_id = self.fluid_grid.len() - 2;
         }
 
-        let mut y_id = (y / GRID_SPACING - 0.5).floor() as i64;
+        let mut y_id = (y / self.grid_spacing - 0.5).floor() as i64;
         if y_id < 0 {
             y_id = 0;
         }
@@ -75,8 +180,8 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
         }
 
         // Relative position from velocity vectors
-        let x_relative_pos = (x - (x_id as f64) * GRID_SPACING) / GRID_SPACING;
-        let y_relative_pos = (y - (y_id as f64 + 0.5) * GRID_SPACING) / GRID_SPACING;
+        let x_relative_pos = (x - (x_id as f64) * self.grid_spacing) / self.grid_spacing;
+        let y_relative_pos = (y - (y_id as f64 + 0.5) * self.grid_spacing) / self.grid_spacing;
         let w00 = 1.0 - x_relative_pos;
         let w10 = 1.0 - y_relative_pos;
         let w01 = x_relative_pos;
@@ -90,7 +195,7 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
     }
 
     pub fn sample_grid_velocity_v(&self, x: f64, y: f64) -> f64 {
-        let mut x_id = (x / GRID_SPACING - 0.5).floor() as i64;
+        let mut x_id = (x / self.grid_spacing - 0.5).floor() as i64;
         if x_id < 0 {
             x_id = 0;
         }
@@ -99,7 +204,7 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
             x_id = self.fluid_grid.len() - 2;
         }
 
-        let mut y_id = (y / GRID_SPACING).floor() as i64;
+        let mut y_id = (y / self.grid_spacing).floor() as i64;
         if y_id < 0 {
             y_id = 0;
         }
@@ -109,8 +214,8 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
         }
 
         // Relative position from velocity vectors
-        let x_relative_pos = (x - (x_id as f64 + 0.5) * GRID_SPACING) / GRID_SPACING;
-        let y_relative_pos = (y - (y_id as f64) * GRID_SPACING) / GRID_SPACING;
+        let x_relative_pos = (x - (x_id as f64 + 0.5) * self.grid_spacing) / self.grid_spacing;
+        let y_relative_pos = (y - (y_id as f64) * self.grid_spacing) / self.grid_spacing;
         let w00 = 1.0 - x_relative_pos;
         let w10 = 1.0 - y_relative_pos;
         let w01 = x_relative_pos;
@@ -123,21 +228,118 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
             + w00 * w11 * self.fluid_grid[x_id][y_id + 1].velocity.1
     }
 
+    /// Sample the dye at any position, stored at the cell centres
+    pub fn sample_grid_dye(&self, x: f64, y: f64) -> f64 {
+        self.sample_cell_centres(x, y, |cell| cell.dye)
+    }
+
+    /// Sample the pressure at any position, stored at the cell centres
+    pub fn sample_grid_pressure(&self, x: f64, y: f64) -> f64 {
+        self.sample_cell_centres(x, y, |cell| cell.pressure)
+    }
+
+    /// Bilinear interpolation of a quantity stored at the cell centres
+    fn sample_cell_centres(&self, x: f64, y: f64, value: impl Fn(&FluidCell) -> f64) -> f64 {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let x = (x / self.grid_spacing - 0.5).clamp(0.0, (grid_size_x - 1) as f64);
+        let y = (y / self.grid_spacing - 0.5).clamp(0.0, (grid_size_y - 1) as f64);
+        let x_id = (x.floor() as usize).min(grid_size_x - 2);
+        let y_id = (y.floor() as usize).min(grid_size_y - 2);
+        let x_relative_pos = x - x_id as f64;
+        let y_relative_pos = y - y_id as f64;
+
+        (1.0 - x_relative_pos) * (1.0 - y_relative_pos) * value(&self.fluid_grid[x_id][y_id])
+            + x_relative_pos * (1.0 - y_relative_pos) * value(&self.fluid_grid[x_id + 1][y_id])
+            + x_relative_pos * y_relative_pos * value(&self.fluid_grid[x_id + 1][y_id + 1])
+            + (1.0 - x_relative_pos) * y_relative_pos * value(&self.fluid_grid[x_id][y_id + 1])
+    }
+
+    /// Accelerate every face between two open cells (gravity or any uniform force)
+    pub fn apply_body_force(&mut self, acceleration: (f64, f64)) {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        for x_id in 1..grid_size_x {
+            for y_id in 1..grid_size_y {
+                let open_cell = self.fluid_grid[x_id][y_id].state.flow_factor();
+                self.fluid_grid[x_id][y_id].velocity.0 += open_cell
+                    * self.fluid_grid[x_id - 1][y_id].state.flow_factor()
+                    * self.timestep
+                    * acceleration.0;
+                self.fluid_grid[x_id][y_id].velocity.1 += open_cell
+                    * self.fluid_grid[x_id][y_id - 1].state.flow_factor()
+                    * self.timestep
+                    * acceleration.1;
+            }
+        }
+    }
+
+    /// Add `velocity` to the faces around `center` (in metres), weighted by a Gaussian of
+    /// standard deviation `radius`. Faces touching a wall or an inflow are left untouched.
+    pub fn apply_velocity_impulse(&mut self, center: (f64, f64), velocity: (f64, f64), radius: f64) {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let weight = |x: f64, y: f64| {
+            let distance_squared = (x - center.0).powi(2) + (y - center.1).powi(2);
+            (-distance_squared / (2.0 * radius * radius)).exp()
+        };
+        // Beyond three standard deviations the weight is negligible
+        let reach = (3.0 * radius / self.grid_spacing).ceil() as isize;
+        let center_x_id = (center.0 / self.grid_spacing) as isize;
+        let center_y_id = (center.1 / self.grid_spacing) as isize;
+        let x_ids = (center_x_id - reach).max(1)..(center_x_id + reach + 1).min(grid_size_x as isize);
+        for x_id in x_ids.map(|x_id| x_id as usize) {
+            let y_ids = (center_y_id - reach).max(1)..(center_y_id + reach + 1).min(grid_size_y as isize);
+            for y_id in y_ids.map(|y_id| y_id as usize) {
+                let open_cell = self.fluid_grid[x_id][y_id].state.flow_factor();
+                let (x, y) = (x_id as f64 * self.grid_spacing, y_id as f64 * self.grid_spacing);
+                self.fluid_grid[x_id][y_id].velocity.0 += open_cell
+                    * self.fluid_grid[x_id - 1][y_id].state.flow_factor()
+                    * weight(x, y + 0.5 * self.grid_spacing)
+                    * velocity.0;
+                self.fluid_grid[x_id][y_id].velocity.1 += open_cell
+                    * self.fluid_grid[x_id][y_id - 1].state.flow_factor()
+                    * weight(x + 0.5 * self.grid_spacing, y)
+                    * velocity.1;
+            }
+        }
+    }
+
+    /// Impose `inflow_velocity` on every face of the inflow cells that is not against a wall
+    pub fn apply_boundary_conditions(&mut self) {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                if self.fluid_grid[x_id][y_id].state != CellState::Inflow {
+                    continue;
+                }
+                if x_id > 0 && self.fluid_grid[x_id - 1][y_id].state != CellState::Wall {
+                    self.fluid_grid[x_id][y_id].velocity.0 = self.inflow_velocity.0;
+                }
+                if x_id + 1 < grid_size_x && self.fluid_grid[x_id + 1][y_id].state != CellState::Wall {
+                    self.fluid_grid[x_id + 1][y_id].velocity.0 = self.inflow_velocity.0;
+                }
+                if y_id > 0 && self.fluid_grid[x_id][y_id - 1].state != CellState::Wall {
+                    self.fluid_grid[x_id][y_id].velocity.1 = self.inflow_velocity.1;
+                }
+                if y_id + 1 < grid_size_y && self.fluid_grid[x_id][y_id + 1].state != CellState::Wall {
+                    self.fluid_grid[x_id][y_id + 1].velocity.1 = self.inflow_velocity.1;
+                }
+            }
+        }
+    }
+
     pub fn solve_grid_incompressibility(&mut self) {
-        let mut first_loop = true;
+        let divergence_before = self.projected_divergence();
         // Resolve fluid grid (Compute divergence and force incompressibility)
-        for _ in 0..40 {
+        for _ in 0..PRESSURE_ITERATIONS {
             for x_id in 1..self.fluid_grid.len() - 1 {
                 for y_id in 1..self.fluid_grid[0].len() - 1 {
-                    if self.fluid_grid[x_id][y_id].state == CellState::Wall {
+                    if self.fluid_grid[x_id][y_id].state != CellState::Fluid {
                         continue;
                     }
 
-                    let number_of_fluid_cell = ((self.fluid_grid[x_id + 1][y_id].state as u8)
-                        + (self.fluid_grid[x_id - 1][y_id].state as u8)
-                        + (self.fluid_grid[x_id][y_id + 1].state as u8)
-                        + (self.fluid_grid[x_id][y_id - 1].state as u8))
-                        as f64;
+                    let number_of_fluid_cell = self.fluid_grid[x_id + 1][y_id].state.flow_factor()
+                        + self.fluid_grid[x_id - 1][y_id].state.flow_factor()
+                        + self.fluid_grid[x_id][y_id + 1].state.flow_factor()
+                        + self.fluid_grid[x_id][y_id - 1].state.flow_factor();
                     if number_of_fluid_cell == 0.0 {
                         continue;
                     }
@@ -146,41 +348,60 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
                         - self.fluid_grid[x_id + 1][y_id].velocity.0
                         - self.fluid_grid[x_id][y_id + 1].velocity.1
                         + self.fluid_grid[x_id][y_id].velocity.1;
-                    if first_loop {
-                        self.fluid_grid[x_id][y_id].divergence = divergence;
-                    }
                     divergence *= 1.9;
 
                     self.fluid_grid[x_id][y_id].velocity.1 -=
-                        ((self.fluid_grid[x_id][y_id - 1].state as u8) as f64) * divergence
+                        self.fluid_grid[x_id][y_id - 1].state.flow_factor() * divergence
                             / number_of_fluid_cell;
                     self.fluid_grid[x_id][y_id + 1].velocity.1 +=
-                        ((self.fluid_grid[x_id][y_id + 1].state as u8) as f64) * divergence
+                        self.fluid_grid[x_id][y_id + 1].state.flow_factor() * divergence
                             / number_of_fluid_cell;
                     self.fluid_grid[x_id][y_id].velocity.0 -=
-                        ((self.fluid_grid[x_id - 1][y_id].state as u8) as f64) * divergence
+                        self.fluid_grid[x_id - 1][y_id].state.flow_factor() * divergence
                             / number_of_fluid_cell;
                     self.fluid_grid[x_id + 1][y_id].velocity.0 +=
-                        ((self.fluid_grid[x_id + 1][y_id].state as u8) as f64) * divergence
+                        self.fluid_grid[x_id + 1][y_id].state.flow_factor() * divergence
                             / number_of_fluid_cell;
 
                     self.fluid_grid[x_id][y_id].pressure -= (divergence / number_of_fluid_cell)
-                        * (FLUID_DENSITY * GRID_SPACING / self.timestep);
+                        * (FLUID_DENSITY * self.grid_spacing / self.timestep);
 
                     // let cell_velocity = (self.fluid_grid[x_id][y_id].velocity.0.powi(2)
                     //     + self.fluid_grid[x_id][y_id].velocity.1.powi(2))
                     // .sqrt() as f32;
                 }
             }
-            first_loop = false;
         }
+
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                let projected = x_id > 0
+                    && y_id > 0
+                    && x_id + 1 < grid_size_x
+                    && y_id + 1 < grid_size_y
+                    && self.fluid_grid[x_id][y_id].state == CellState::Fluid;
+                self.fluid_grid[x_id][y_id].divergence =
+                    if projected { self.velocity_divergence(x_id, y_id) } else { 0.0 };
+            }
+        }
+        self.projection = ProjectionStats {
+            iterations: PRESSURE_ITERATIONS,
+            divergence_before,
+            divergence_after: self.projected_divergence(),
+        };
+        log::trace!(target: crate::logging::SOLVER, "{:?}", self.projection);
     }
 
     pub fn apply_advection(&mut self) {
-        let mut new_grid = self.fluid_grid;
+        let mut new_grid = self.fluid_grid.clone();
         for x_id in 1..self.fluid_grid.len() - 1 {
             for y_id in 1..self.fluid_grid[0].len() - 1 {
-                if self.fluid_grid[x_id][y_id].state != CellState::Wall
+                if self.fluid_grid[x_id][y_id].state == CellState::Inflow
+                    || self.fluid_grid[x_id - 1][y_id].state == CellState::Inflow
+                {
+                    // Inflow faces keep their imposed velocity
+                } else if self.fluid_grid[x_id][y_id].state != CellState::Wall
                     && self.fluid_grid[x_id - 1][y_id].state != CellState::Wall
                 {
                     // Compute for U point
@@ -191,8 +412,8 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
                         + self.fluid_grid[x_id - 1][y_id + 1].velocity.1)
                         / 4.0;
                     let last_point = (
-                        (x_id as f64) * GRID_SPACING - self.timestep * u,
-                        (y_id as f64 + 0.5) * GRID_SPACING - self.timestep * v,
+                        (x_id as f64) * self.grid_spacing - self.timestep * u,
+                        (y_id as f64 + 0.5) * self.grid_spacing - self.timestep * v,
                     );
                     new_grid[x_id][y_id].velocity.0 =
                         self.sample_grid_velocity_u(last_point.0, last_point.1);
@@ -200,7 +421,11 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
                     new_grid[x_id][y_id].velocity.0 = 0.0;
                 }
 
-                if self.fluid_grid[x_id][y_id].state != CellState::Wall
+                if self.fluid_grid[x_id][y_id].state == CellState::Inflow
+                    || self.fluid_grid[x_id][y_id - 1].state == CellState::Inflow
+                {
+                    // Inflow faces keep their imposed velocity
+                } else if self.fluid_grid[x_id][y_id].state != CellState::Wall
                     && self.fluid_grid[x_id][y_id - 1].state != CellState::Wall
                 {
                     // Compute for V point
@@ -211,14 +436,24 @@ impl<const GRID_SIZE_X: usize, const GRID_SIZE_Y: usize> FluidDomain<GRID_SIZE_X
                         / 4.0;
                     let v = self.fluid_grid[x_id][y_id].velocity.1;
                     let last_point = (
-                        (x_id as f64 + 0.5) * GRID_SPACING - self.timestep * u,
-                        (y_id as f64) * GRID_SPACING - self.timestep * v,
+                        (x_id as f64 + 0.5) * self.grid_spacing - self.timestep * u,
+                        (y_id as f64) * self.grid_spacing - self.timestep * v,
                     );
                     new_grid[x_id][y_id].velocity.1 =
                         self.sample_grid_velocity_v(last_point.0, last_point.1);
                 } else {
                     new_grid[x_id][y_id].velocity.1 = 0.0;
                 }
+
+                if self.fluid_grid[x_id][y_id].state != CellState::Wall {
+                    // Compute for dye at the cell centre
+                    let (u, v) = self.cell_velocity(x_id, y_id);
+                    let last_point = (
+                        (x_id as f64 + 0.5) * self.grid_spacing - self.timestep * u,
+                        (y_id as f64 + 0.5) * self.grid_spacing - self.timestep * v,
+                    );
+                    new_grid[x_id][y_id].dye = self.sample_grid_dye(last_point.0, last_point.1);
+                }
             }
         }
         self.fluid_grid = new_grid;
@@ -0,0 +1,116 @@
+use super::{CellState, FluidDomain};
+
+/// Over-relaxed Gauss-Seidel sweeps refining the stream function
+const STREAM_FUNCTION_ITERATIONS: usize = 100;
+const STREAM_FUNCTION_OVER_RELAXATION: f64 = 1.9;
+
+/// Velocity gradient at the centre of a cell (1/s)
+struct VelocityGradient {
+    du_dx: f64,
+    du_dy: f64,
+    dv_dx: f64,
+    dv_dy: f64,
+}
+
+// Quantities derived from the staggered velocities. du/dx and dv/dy are exact at the cell
+// centre; du/dy and dv/dx are exact at the cell corners, where the u and v faces meet, and
+// averaged over the four corners of the cell. Border cells have no gradient and get 0.
+
+impl FluidDomain {
+    /// Velocity magnitude at the centre of a cell (m/s)
+    pub fn speed(&self, x_id: usize, y_id: usize) -> f64 {
+        let (u, v) = self.cell_velocity(x_id, y_id);
+        u.hypot(v)
+    }
+
+    /// Vorticity dv/dx - du/dy at the centre of a cell (1/s).
+    /// With y pointing down, positive values turn clockwise on screen.
+    pub fn vorticity(&self, x_id: usize, y_id: usize) -> f64 {
+        self.velocity_gradient(x_id, y_id)
+            .map_or(0.0, |gradient| gradient.dv_dx - gradient.du_dy)
+    }
+
+    /// Enstrophy of the fluid cells, half the integral of the squared vorticity (m²/s²)
+    pub fn enstrophy(&self) -> f64 {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let mut enstrophy = 0.0;
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                if self.fluid_grid[x_id][y_id].state == CellState::Fluid {
+                    enstrophy += 0.5 * self.vorticity(x_id, y_id).powi(2) * self.grid_spacing * self.grid_spacing;
+                }
+            }
+        }
+        enstrophy
+    }
+
+    /// Q-criterion (|rotation|² - |strain|²) / 2 at the centre of a cell (1/s²),
+    /// positive where rotation dominates, as in vortex cores
+    pub fn q_criterion(&self, x_id: usize, y_id: usize) -> f64 {
+        self.velocity_gradient(x_id, y_id).map_or(0.0, |gradient| {
+            -0.5 * (gradient.du_dx.powi(2) + gradient.dv_dy.powi(2)) - gradient.du_dy * gradient.dv_dx
+        })
+    }
+
+    /// Stream function ψ at every cell centre (m²/s), with u = dψ/dy and v = -dψ/dx in grid axes.
+    ///
+    /// ψ is first integrated from the velocities (along the top row, then down every column),
+    /// which sets the border values, then refined by solving the Poisson equation ∇²ψ = -ω
+    /// inside the grid. Its isolines are the streamlines of the flow.
+    pub fn stream_function(&self) -> Vec<Vec<f64>> {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let spacing = self.grid_spacing;
+
+        let mut stream_function = vec![vec![0.0; grid_size_y]; grid_size_x];
+        for x_id in 1..grid_size_x {
+            // Flow through the face between the two cell centres of the top row
+            let v = self.sample_grid_velocity_v(x_id as f64 * spacing, 0.5 * spacing);
+            stream_function[x_id][0] = stream_function[x_id - 1][0] - v * spacing;
+        }
+        for (x_id, column) in stream_function.iter_mut().enumerate() {
+            for y_id in 1..grid_size_y {
+                let u = self.sample_grid_velocity_u((x_id as f64 + 0.5) * spacing, y_id as f64 * spacing);
+                column[y_id] = column[y_id - 1] + u * spacing;
+            }
+        }
+
+        let mut vorticity = vec![vec![0.0; grid_size_y]; grid_size_x];
+        for (x_id, column) in vorticity.iter_mut().enumerate() {
+            for (y_id, value) in column.iter_mut().enumerate() {
+                *value = self.vorticity(x_id, y_id);
+            }
+        }
+        for _ in 0..STREAM_FUNCTION_ITERATIONS {
+            for x_id in 1..grid_size_x - 1 {
+                for y_id in 1..grid_size_y - 1 {
+                    let neighbour_sum = stream_function[x_id - 1][y_id]
+                        + stream_function[x_id + 1][y_id]
+                        + stream_function[x_id][y_id - 1]
+                        + stream_function[x_id][y_id + 1];
+                    let target = (neighbour_sum + spacing * spacing * vorticity[x_id][y_id]) / 4.0;
+                    stream_function[x_id][y_id] +=
+                        STREAM_FUNCTION_OVER_RELAXATION * (target - stream_function[x_id][y_id]);
+                }
+            }
+        }
+        stream_function
+    }
+
+    fn velocity_gradient(&self, x_id: usize, y_id: usize) -> Option<VelocityGradient> {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        if x_id == 0 || y_id == 0 || x_id + 1 >= grid_size_x || y_id + 1 >= grid_size_y {
+            return None;
+        }
+        let u = |x_id: usize, y_id: usize| self.fluid_grid[x_id][y_id].velocity.0;
+        let v = |x_id: usize, y_id: usize| self.fluid_grid[x_id][y_id].velocity.1;
+        let corners = [(x_id, y_id), (x_id + 1, y_id), (x_id, y_id + 1), (x_id + 1, y_id + 1)];
+        let du_dy: f64 = corners.iter().map(|&(i, j)| u(i, j) - u(i, j - 1)).sum();
+        let dv_dx: f64 = corners.iter().map(|&(i, j)| v(i, j) - v(i - 1, j)).sum();
+        Some(VelocityGradient {
+            du_dx: (u(x_id + 1, y_id) - u(x_id, y_id)) / self.grid_spacing,
+            du_dy: du_dy / (4.0 * self.grid_spacing),
+            dv_dx: dv_dx / (4.0 * self.grid_spacing),
+            dv_dy: (v(x_id, y_id + 1) - v(x_id, y_id)) / self.grid_spacing,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+use super::{CellState, FluidDomain};
+
+/// Divergence norms over the fluid cells the pressure solver projects, those off the grid
+/// border (1/s)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DivergenceNorms {
+    /// Largest absolute divergence
+    pub max: f64,
+    /// Root mean square divergence
+    pub rms: f64,
+}
+
+/// Outcome of the last `solve_grid_incompressibility`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProjectionStats {
+    pub iterations: usize,
+    pub divergence_before: DivergenceNorms,
+    pub divergence_after: DivergenceNorms,
+}
+
+/// Global quantities of a fluid domain after a step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Diagnostics {
+    /// J per metre of depth
+    pub kinetic_energy: f64,
+    /// m²/s²
+    pub enstrophy: f64,
+    /// Largest cell speed (m/s)
+    pub max_speed: f64,
+    pub projection: ProjectionStats,
+    /// Area of the fluid cells (m² per metre of depth), which follows the liquid in liquid scenes.
+    /// Cells are counted whole, so changes smaller than a cell do not show.
+    pub fluid_volume: f64,
+    /// Largest face velocity of the fluid cells times the timestep over the grid spacing: the
+    /// most cells the advection traces back in one step
+    pub cfl: f64,
+}
+
+impl FluidDomain {
+    /// Global quantities of the current state, the projection ones being those of the last
+    /// pressure solve
+    pub fn diagnostics(&self) -> Diagnostics {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let (mut fluid_cells, mut max_speed, mut max_face_velocity) = (0usize, 0f64, 0f64);
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                if self.fluid_grid[x_id][y_id].state != CellState::Fluid {
+                    continue;
+                }
+                fluid_cells += 1;
+                max_speed = max_speed.max(self.speed(x_id, y_id));
+                // Left and top faces, then the right and bottom ones stored in the next cells
+                let (u, v) = self.fluid_grid[x_id][y_id].velocity;
+                max_face_velocity = max_face_velocity.max(u.abs()).max(v.abs());
+                if x_id + 1 < grid_size_x {
+                    max_face_velocity = max_face_velocity.max(self.fluid_grid[x_id + 1][y_id].velocity.0.abs());
+                }
+                if y_id + 1 < grid_size_y {
+                    max_face_velocity = max_face_velocity.max(self.fluid_grid[x_id][y_id + 1].velocity.1.abs());
+                }
+            }
+        }
+        Diagnostics {
+            kinetic_energy: self.kinetic_energy(),
+            enstrophy: self.enstrophy(),
+            max_speed,
+            projection: self.projection,
+            fluid_volume: fluid_cells as f64 * self.grid_spacing * self.grid_spacing,
+            cfl: max_face_velocity * self.timestep / self.grid_spacing,
+        }
+    }
+
+    /// Divergence norms of the current velocities over the cells the pressure solver projects
+    pub fn projected_divergence(&self) -> DivergenceNorms {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let (mut max, mut sum_squares, mut cells) = (0f64, 0.0, 0usize);
+        for x_id in 1..grid_size_x - 1 {
+            for y_id in 1..grid_size_y - 1 {
+                if self.fluid_grid[x_id][y_id].state != CellState::Fluid {
+                    continue;
+                }
+                let divergence = self.velocity_divergence(x_id, y_id);
+                max = max.max(divergence.abs());
+                sum_squares += divergence * divergence;
+                cells += 1;
+            }
+        }
+        DivergenceNorms {
+            max,
+            rms: if cells == 0 { 0.0 } else { (sum_squares / cells as f64).sqrt() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Closed box of walls whose fluid cells get pseudo-random velocities in [-1, 1]
+    fn closed_box_with_random_velocities() -> FluidDomain {
+        let (grid_size_x, grid_size_y) = (24, 16);
+        let mut fluid_domain = FluidDomain::new(grid_size_x, grid_size_y, 0.01);
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                if x_id == 0 || y_id == 0 || x_id == grid_size_x - 1 || y_id == grid_size_y - 1 {
+                    fluid_domain.set_cell_state(x_id, y_id, CellState::Wall);
+                }
+            }
+        }
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move || {
+            // xorshift64
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+        };
+        for x_id in 1..grid_size_x - 1 {
+            for y_id in 1..grid_size_y - 1 {
+                fluid_domain.fluid_grid[x_id][y_id].velocity = (random(), random());
+            }
+        }
+        // No flow through the walls
+        for y_id in 0..grid_size_y {
+            fluid_domain.fluid_grid[1][y_id].velocity.0 = 0.0;
+            fluid_domain.fluid_grid[grid_size_x - 1][y_id].velocity.0 = 0.0;
+        }
+        for x_id in 0..grid_size_x {
+            fluid_domain.fluid_grid[x_id][1].velocity.1 = 0.0;
+            fluid_domain.fluid_grid[x_id][grid_size_y - 1].velocity.1 = 0.0;
+        }
+        fluid_domain
+    }
+
+    fn step(fluid_domain: &mut FluidDomain) {
+        for cell in fluid_domain.fluid_grid.iter_mut().flatten() {
+            cell.pressure = 0.0;
+        }
+        fluid_domain.solve_grid_incompressibility();
+        fluid_domain.apply_advection();
+    }
+
+    #[test]
+    fn projection_reduces_divergence() {
+        let mut fluid_domain = closed_box_with_random_velocities();
+        fluid_domain.solve_grid_incompressibility();
+        let projection = fluid_domain.diagnostics().projection;
+        assert!(projection.divergence_before.max > 1.0);
+        assert!(projection.divergence_after.max < 0.1 * projection.divergence_before.max);
+        assert!(projection.divergence_after.rms < 0.1 * projection.divergence_before.rms);
+    }
+
+    #[test]
+    fn closed_box_keeps_its_fluid_volume() {
+        let mut fluid_domain = closed_box_with_random_velocities();
+        let initial_volume = fluid_domain.diagnostics().fluid_volume;
+        assert_eq!(initial_volume, 22.0 * 14.0);
+        for _ in 0..20 {
+            step(&mut fluid_domain);
+            assert_eq!(fluid_domain.diagnostics().fluid_volume, initial_volume);
+        }
+    }
+
+    #[test]
+    fn closed_box_does_not_gain_kinetic_energy() {
+        let mut fluid_domain = closed_box_with_random_velocities();
+        fluid_domain.solve_grid_incompressibility();
+        let mut kinetic_energy = fluid_domain.diagnostics().kinetic_energy;
+        for _ in 0..20 {
+            step(&mut fluid_domain);
+            let next_kinetic_energy = fluid_domain.diagnostics().kinetic_energy;
+            assert!(next_kinetic_energy <= kinetic_energy * (1.0 + 1e-9));
+            kinetic_energy = next_kinetic_energy;
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use super::{CellState, FluidDomain};
+
+/// Marker particles carrying the liquid, used to track a free surface on top of a
+/// `FluidDomain` (PIC/FLIP transfer, cells without particles become `CellState::Air`).
+pub struct LiquidParticles {
+    pub positions: Vec<(f64, f64)>,
+    pub velocities: Vec<(f64, f64)>,
+    /// External acceleration applied to every particle (grid y axis points down)
+    pub gravity: (f64, f64),
+    /// 0.0 is pure PIC (dissipative), 1.0 is pure FLIP (noisy)
+    pub flip_ratio: f64,
+}
+impl LiquidParticles {
+    pub fn new(gravity: (f64, f64)) -> Self {
+        LiquidParticles {
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            gravity,
+            flip_ratio: 0.9,
+        }
+    }
+
+    /// Seed `per_axis`² particles at rest in every cell of the given rectangle (in cell ids).
+    pub fn fill_rectangle(
+        &mut self,
+        fluid_domain: &FluidDomain,
+        x_ids: std::ops::Range<usize>,
+        y_ids: std::ops::Range<usize>,
+        per_axis: usize,
+    ) {
+        for x_id in x_ids {
+            for y_id in y_ids.clone() {
+                self.fill_cell(fluid_domain, x_id, y_id, per_axis);
+            }
+        }
+    }
+
+    /// Seed `per_axis`² particles at rest in one cell.
+    pub fn fill_cell(
+        &mut self,
+        fluid_domain: &FluidDomain,
+        x_id: usize,
+        y_id: usize,
+        per_axis: usize,
+    ) {
+        let grid_spacing = fluid_domain.grid_spacing;
+        let sub_spacing = grid_spacing / per_axis as f64;
+        for i in 0..per_axis {
+            for j in 0..per_axis {
+                self.positions.push((
+                    x_id as f64 * grid_spacing + (i as f64 + 0.5) * sub_spacing,
+                    y_id as f64 * grid_spacing + (j as f64 + 0.5) * sub_spacing,
+                ));
+                self.velocities.push((0.0, 0.0));
+            }
+        }
+    }
+
+    /// Largest x coordinate reached by the liquid.
+    pub fn front_position(&self) -> f64 {
+        self.positions
+            .iter()
+            .map(|p| p.0)
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    /// Same Gaussian impulse as `FluidDomain::apply_velocity_impulse`, given to the particles.
+    pub fn apply_velocity_impulse(&mut self, center: (f64, f64), velocity: (f64, f64), radius: f64) {
+        for (position, particle_velocity) in self.positions.iter().zip(self.velocities.iter_mut()) {
+            let distance_squared = (position.0 - center.0).powi(2) + (position.1 - center.1).powi(2);
+            let weight = (-distance_squared / (2.0 * radius * radius)).exp();
+            particle_velocity.0 += weight * velocity.0;
+            particle_velocity.1 += weight * velocity.1;
+        }
+    }
+
+    /// Advance the liquid by one timestep of the domain.
+    pub fn step(&mut self, fluid_domain: &mut FluidDomain) {
+        self.integrate_particles(fluid_domain);
+        self.update_cell_states(fluid_domain);
+        self.transfer_to_grid(fluid_domain);
+
+        let previous_grid = fluid_domain.fluid_grid.clone();
+        for column in fluid_domain.fluid_grid.iter_mut() {
+            for cell in column.iter_mut() {
+                cell.pressure = 0.0;
+            }
+        }
+        fluid_domain.solve_grid_incompressibility();
+
+        self.transfer_from_grid(fluid_domain, &previous_grid);
+    }
+
+    fn integrate_particles(&mut self, fluid_domain: &FluidDomain) {
+        let timestep = fluid_domain.timestep;
+        let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+        let grid_spacing = fluid_domain.grid_spacing;
+        // Keep particles one cell away from the domain border, the solver never touches it
+        let margin = 0.01 * grid_spacing;
+        let (min_x, max_x) = (
+            grid_spacing + margin,
+            (grid_size_x - 1) as f64 * grid_spacing - margin,
+        );
+        let (min_y, max_y) = (
+            grid_spacing + margin,
+            (grid_size_y - 1) as f64 * grid_spacing - margin,
+        );
+
+        for (position, velocity) in self.positions.iter_mut().zip(self.velocities.iter_mut()) {
+            velocity.0 += timestep * self.gravity.0;
+            velocity.1 += timestep * self.gravity.1;
+            let last_position = *position;
+            position.0 += timestep * velocity.0;
+            position.1 += timestep * velocity.1;
+
+            if position.0 < min_x || position.0 > max_x {
+                position.0 = position.0.clamp(min_x, max_x);
+                velocity.0 = 0.0;
+            }
+            if position.1 < min_y || position.1 > max_y {
+                position.1 = position.1.clamp(min_y, max_y);
+                velocity.1 = 0.0;
+            }
+
+            // Inner obstacles: go back to the last valid position
+            let (x_id, y_id) = cell_of(*position, grid_spacing);
+            if fluid_domain.fluid_grid[x_id][y_id].state == CellState::Wall {
+                *position = last_position;
+                *velocity = (0.0, 0.0);
+            }
+        }
+    }
+
+    fn update_cell_states(&self, fluid_domain: &mut FluidDomain) {
+        let grid_spacing = fluid_domain.grid_spacing;
+        for column in fluid_domain.fluid_grid.iter_mut() {
+            for cell in column.iter_mut() {
+                if cell.state == CellState::Fluid {
+                    cell.state = CellState::Air;
+                }
+            }
+        }
+        for position in self.positions.iter() {
+            let (x_id, y_id) = cell_of(*position, grid_spacing);
+            if fluid_domain.fluid_grid[x_id][y_id].state == CellState::Air {
+                fluid_domain.fluid_grid[x_id][y_id].state = CellState::Fluid;
+            }
+        }
+    }
+
+    fn transfer_to_grid(&self, fluid_domain: &mut FluidDomain) {
+        let grid_size = fluid_domain.grid_size();
+        let grid_spacing = fluid_domain.grid_spacing;
+        let mut velocity_sum = vec![vec![(0f64, 0f64); grid_size.1]; grid_size.0];
+        let mut weight_sum = vec![vec![(0f64, 0f64); grid_size.1]; grid_size.0];
+        for (position, velocity) in self.positions.iter().zip(self.velocities.iter()) {
+            for (x_id, y_id, weight) in face_weights(grid_size, grid_spacing, *position, U_FACE) {
+                velocity_sum[x_id][y_id].0 += weight * velocity.0;
+                weight_sum[x_id][y_id].0 += weight;
+            }
+            for (x_id, y_id, weight) in face_weights(grid_size, grid_spacing, *position, V_FACE) {
+                velocity_sum[x_id][y_id].1 += weight * velocity.1;
+                weight_sum[x_id][y_id].1 += weight;
+            }
+        }
+
+        for x_id in 0..grid_size.0 {
+            for y_id in 0..grid_size.1 {
+                let cell_state = fluid_domain.fluid_grid[x_id][y_id].state;
+                let left_state = if x_id > 0 {
+                    fluid_domain.fluid_grid[x_id - 1][y_id].state
+                } else {
+                    CellState::Wall
+                };
+                let top_state = if y_id > 0 {
+                    fluid_domain.fluid_grid[x_id][y_id - 1].state
+                } else {
+                    CellState::Wall
+                };
+                let cell = &mut fluid_domain.fluid_grid[x_id][y_id];
+
+                cell.velocity.0 = if cell_state == CellState::Wall || left_state == CellState::Wall
+                {
+                    0.0
+                } else if weight_sum[x_id][y_id].0 > 0.0 {
+                    velocity_sum[x_id][y_id].0 / weight_sum[x_id][y_id].0
+                } else {
+                    0.0
+                };
+                cell.velocity.1 = if cell_state == CellState::Wall || top_state == CellState::Wall {
+                    0.0
+                } else if weight_sum[x_id][y_id].1 > 0.0 {
+                    velocity_sum[x_id][y_id].1 / weight_sum[x_id][y_id].1
+                } else {
+                    0.0
+                };
+            }
+        }
+    }
+
+    fn transfer_from_grid(
+        &mut self,
+        fluid_domain: &FluidDomain,
+        previous_grid: &[Vec<super::FluidCell>],
+    ) {
+        let grid_size = fluid_domain.grid_size();
+        let grid_spacing = fluid_domain.grid_spacing;
+        let grid = &fluid_domain.fluid_grid;
+        // A face only carries a meaningful velocity if it touches the liquid
+        let is_fluid = |x_id: usize, y_id: usize| grid[x_id][y_id].state == CellState::Fluid;
+        let valid_u = |x_id: usize, y_id: usize| {
+            is_fluid(x_id, y_id) || (x_id > 0 && is_fluid(x_id - 1, y_id))
+        };
+        let valid_v = |x_id: usize, y_id: usize| {
+            is_fluid(x_id, y_id) || (y_id > 0 && is_fluid(x_id, y_id - 1))
+        };
+
+        for (position, velocity) in self.positions.iter().zip(self.velocities.iter_mut()) {
+            let (mut weight_total, mut pic, mut correction) = (0.0, 0.0, 0.0);
+            for (x_id, y_id, weight) in face_weights(grid_size, grid_spacing, *position, U_FACE) {
+                if valid_u(x_id, y_id) {
+                    weight_total += weight;
+                    pic += weight * grid[x_id][y_id].velocity.0;
+                    correction += weight
+                        * (grid[x_id][y_id].velocity.0 - previous_grid[x_id][y_id].velocity.0);
+                }
+            }
+            if weight_total > 0.0 {
+                pic /= weight_total;
+                let flip = velocity.0 + correction / weight_total;
+                velocity.0 = (1.0 - self.flip_ratio) * pic + self.flip_ratio * flip;
+            }
+
+            let (mut weight_total, mut pic, mut correction) = (0.0, 0.0, 0.0);
+            for (x_id, y_id, weight) in face_weights(grid_size, grid_spacing, *position, V_FACE) {
+                if valid_v(x_id, y_id) {
+                    weight_total += weight;
+                    pic += weight * grid[x_id][y_id].velocity.1;
+                    correction += weight
+                        * (grid[x_id][y_id].velocity.1 - previous_grid[x_id][y_id].velocity.1);
+                }
+            }
+            if weight_total > 0.0 {
+                pic /= weight_total;
+                let flip = velocity.1 + correction / weight_total;
+                velocity.1 = (1.0 - self.flip_ratio) * pic + self.flip_ratio * flip;
+            }
+        }
+    }
+}
+
+/// Offset of the u (resp. v) samples from the cell corner, in cells.
+const U_FACE: (f64, f64) = (0.0, 0.5);
+const V_FACE: (f64, f64) = (0.5, 0.0);
+
+fn cell_of(position: (f64, f64), grid_spacing: f64) -> (usize, usize) {
+    (
+        (position.0 / grid_spacing).floor().max(0.0) as usize,
+        (position.1 / grid_spacing).floor().max(0.0) as usize,
+    )
+}
+
+/// Bilinear weights of the four faces surrounding `position` for the staggered component
+/// located at `face_offset`.
+fn face_weights(
+    grid_size: (usize, usize),
+    grid_spacing: f64,
+    position: (f64, f64),
+    face_offset: (f64, f64),
+) -> [(usize, usize, f64); 4] {
+    let x = (position.0 / grid_spacing - face_offset.0).clamp(0.0, (grid_size.0 - 1) as f64);
+    let y = (position.1 / grid_spacing - face_offset.1).clamp(0.0, (grid_size.1 - 1) as f64);
+    let x_id = (x.floor() as usize).min(grid_size.0 - 2);
+    let y_id = (y.floor() as usize).min(grid_size.1 - 2);
+    let tx = x - x_id as f64;
+    let ty = y - y_id as f64;
+
+    [
+        (x_id, y_id, (1.0 - tx) * (1.0 - ty)),
+        (x_id + 1, y_id, tx * (1.0 - ty)),
+        (x_id, y_id + 1, (1.0 - tx) * ty),
+        (x_id + 1, y_id + 1, tx * ty),
+    ]
+}
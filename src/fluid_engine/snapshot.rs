@@ -0,0 +1,232 @@
+use super::{CellState, FluidDomain, MAX_GRID_SIZE, MIN_GRID_SIZE};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"FLUIDSNP";
+/// Bumped every time the layout below changes
+pub const SNAPSHOT_VERSION: u32 = 2;
+/// Bytes of one cell: state, then 5 x f64
+const CELL_RECORD_SIZE: usize = 1 + 5 * 8;
+/// Most scene state values a snapshot may hold
+const MAX_SCENE_STATE_VALUES: usize = 1024;
+
+// Layout (little endian):
+//   magic, version: u32, step: u64, scene state length: u32, scene state: length x f64,
+//   grid size x: u64, grid size y: u64,
+//   timestep: f64, grid spacing: f64, inflow velocity: 2 x f64,
+//   then for every cell, column by column:
+//   state: u8, u: f64, v: f64, pressure: f64, divergence: f64, dye: f64
+
+/// What a snapshot holds besides the fluid domain, to continue the run it was taken from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SnapshotHeader {
+    /// Steps done when the snapshot was taken
+    pub step: usize,
+    /// Scene specific state, see `Simulation::scene_state`
+    pub scene_state: Vec<f64>,
+}
+
+impl FluidDomain {
+    pub fn save_snapshot(&self, header: &SnapshotHeader, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_snapshot(header, &mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load_snapshot(path: &Path) -> io::Result<(Self, SnapshotHeader)> {
+        Self::read_snapshot(&std::fs::read(path)?)
+    }
+
+    pub fn write_snapshot<W: Write>(&self, header: &SnapshotHeader, writer: &mut W) -> io::Result<()> {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        writer.write_all(&(header.step as u64).to_le_bytes())?;
+        writer.write_all(&(header.scene_state.len() as u32).to_le_bytes())?;
+        for value in header.scene_state.iter() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&(grid_size_x as u64).to_le_bytes())?;
+        writer.write_all(&(grid_size_y as u64).to_le_bytes())?;
+        for value in [
+            self.timestep,
+            self.grid_spacing,
+            self.inflow_velocity.0,
+            self.inflow_velocity.1,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        for cell in self.fluid_grid.iter().flatten() {
+            writer.write_all(&[cell.state as u8])?;
+            for value in [
+                cell.velocity.0,
+                cell.velocity.1,
+                cell.pressure,
+                cell.divergence,
+                cell.dye,
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Snapshot held by `bytes`, checked against their length before the grid is allocated
+    pub fn read_snapshot(bytes: &[u8]) -> io::Result<(Self, SnapshotHeader)> {
+        let reader = &mut &bytes[..];
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(invalid_data("not a fluid snapshot".to_string()));
+        }
+        let version = u32::from_le_bytes(read_bytes(reader)?);
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported snapshot version {} (expected {})",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let step = u64::from_le_bytes(read_bytes(reader)?) as usize;
+        let scene_state_length = u32::from_le_bytes(read_bytes(reader)?) as usize;
+        if scene_state_length > MAX_SCENE_STATE_VALUES {
+            return Err(invalid_data(format!("invalid scene state length {}", scene_state_length)));
+        }
+        let scene_state = (0..scene_state_length)
+            .map(|_| read_f64(reader))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let grid_size_x = u64::from_le_bytes(read_bytes(reader)?);
+        let grid_size_y = u64::from_le_bytes(read_bytes(reader)?);
+        // The cells follow the 4 f64 of the domain parameters
+        let expected_length = grid_size_x
+            .checked_mul(grid_size_y)
+            .and_then(|cells| cells.checked_mul(CELL_RECORD_SIZE as u64))
+            .and_then(|cells_length| cells_length.checked_add(4 * 8));
+        let grid_sizes = MIN_GRID_SIZE as u64..=MAX_GRID_SIZE as u64;
+        if !grid_sizes.contains(&grid_size_x)
+            || !grid_sizes.contains(&grid_size_y)
+            || expected_length != Some(reader.len() as u64)
+        {
+            return Err(invalid_data(format!(
+                "invalid grid size {}x{} for {} bytes of data",
+                grid_size_x,
+                grid_size_y,
+                reader.len()
+            )));
+        }
+        let (grid_size_x, grid_size_y) = (grid_size_x as usize, grid_size_y as usize);
+        let timestep = read_f64(reader)?;
+        let mut fluid_domain = FluidDomain::new(grid_size_x, grid_size_y, timestep);
+        fluid_domain.grid_spacing = read_f64(reader)?;
+        fluid_domain.inflow_velocity = (read_f64(reader)?, read_f64(reader)?);
+
+        for cell in fluid_domain.fluid_grid.iter_mut().flatten() {
+            let [state] = read_bytes(reader)?;
+            cell.state = CellState::from_id(state)
+                .ok_or_else(|| invalid_data(format!("invalid cell state {}", state)))?;
+            cell.velocity = (read_f64(reader)?, read_f64(reader)?);
+            cell.pressure = read_f64(reader)?;
+            cell.divergence = read_f64(reader)?;
+            cell.dye = read_f64(reader)?;
+        }
+        Ok((fluid_domain, SnapshotHeader { step, scene_state }))
+    }
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    Ok(f64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Offset of the scene state length, after the magic, version and step
+    const SCENE_STATE_LENGTH_OFFSET: usize = 8 + 4 + 8;
+
+    fn snapshot_bytes() -> (FluidDomain, SnapshotHeader, Vec<u8>) {
+        let mut fluid_domain = FluidDomain::new(7, 5, 0.02);
+        fluid_domain.grid_spacing = 0.5;
+        fluid_domain.inflow_velocity = (1.5, -0.25);
+        for (cell_id, cell) in fluid_domain.fluid_grid.iter_mut().flatten().enumerate() {
+            let value = cell_id as f64;
+            cell.velocity = (value * 0.1, -value);
+            cell.pressure = value * 10.0;
+            cell.divergence = value * 1e-3;
+            cell.dye = value / 35.0;
+        }
+        fluid_domain.set_cell_state(0, 2, CellState::Wall);
+        fluid_domain.set_cell_state(0, 3, CellState::Inflow);
+        fluid_domain.set_cell_state(6, 3, CellState::Outflow);
+        let header = SnapshotHeader {
+            step: 1234,
+            scene_state: vec![1.0, -2.5],
+        };
+        let mut bytes = Vec::new();
+        fluid_domain.write_snapshot(&header, &mut bytes).unwrap();
+        (fluid_domain, header, bytes)
+    }
+
+    fn error_kind(bytes: &[u8]) -> io::ErrorKind {
+        FluidDomain::read_snapshot(bytes).err().expect("snapshot should be rejected").kind()
+    }
+
+    #[test]
+    fn read_returns_what_was_written() {
+        let (fluid_domain, header, bytes) = snapshot_bytes();
+        let (read_domain, read_header) = FluidDomain::read_snapshot(&bytes).unwrap();
+        assert_eq!(read_header, header);
+        assert_eq!(read_domain.grid_size(), fluid_domain.grid_size());
+        assert_eq!(read_domain.timestep, fluid_domain.timestep);
+        assert_eq!(read_domain.grid_spacing, fluid_domain.grid_spacing);
+        assert_eq!(read_domain.inflow_velocity, fluid_domain.inflow_velocity);
+        let cells = fluid_domain.fluid_grid.iter().flatten();
+        for (read_cell, cell) in read_domain.fluid_grid.iter().flatten().zip(cells) {
+            assert_eq!(read_cell.state, cell.state);
+            assert_eq!(read_cell.velocity, cell.velocity);
+            assert_eq!(read_cell.pressure, cell.pressure);
+            assert_eq!(read_cell.divergence, cell.divergence);
+            assert_eq!(read_cell.dye, cell.dye);
+        }
+    }
+
+    #[test]
+    fn wrong_magic_or_version_is_rejected() {
+        let (_, _, bytes) = snapshot_bytes();
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert_eq!(error_kind(&wrong_magic), io::ErrorKind::InvalidData);
+        let mut wrong_version = bytes;
+        wrong_version[8..12].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert_eq!(error_kind(&wrong_version), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_snapshot_is_rejected() {
+        let (_, _, bytes) = snapshot_bytes();
+        // In the cells, then in the header
+        assert_eq!(error_kind(&bytes[..bytes.len() - 1]), io::ErrorKind::InvalidData);
+        assert_eq!(error_kind(&bytes[..SCENE_STATE_LENGTH_OFFSET + 2]), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn oversized_scene_state_is_rejected() {
+        let (_, _, mut bytes) = snapshot_bytes();
+        let length = (MAX_SCENE_STATE_VALUES as u32 + 1).to_le_bytes();
+        bytes[SCENE_STATE_LENGTH_OFFSET..SCENE_STATE_LENGTH_OFFSET + 4].copy_from_slice(&length);
+        assert_eq!(error_kind(&bytes), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,100 @@
+use super::{CellState, FluidDomain};
+
+/// Below this speed (m/s) the flow direction is undefined and a streamline stops
+pub const STAGNATION_SPEED: f64 = 1e-9;
+
+impl FluidDomain {
+    /// Velocity at a point (metres), interpolated from the staggered faces
+    pub fn sample_velocity(&self, position: (f64, f64)) -> (f64, f64) {
+        (
+            self.sample_grid_velocity_u(position.0, position.1),
+            self.sample_grid_velocity_v(position.0, position.1),
+        )
+    }
+
+    /// True if `position` (metres) is inside the grid, in a cell tracers can move through
+    pub fn is_open_position(&self, position: (f64, f64)) -> bool {
+        let (grid_size_x, grid_size_y) = self.grid_size();
+        let x = position.0 / self.grid_spacing;
+        let y = position.1 / self.grid_spacing;
+        if x < 0.0 || y < 0.0 || x >= grid_size_x as f64 || y >= grid_size_y as f64 {
+            return false;
+        }
+        matches!(
+            self.fluid_grid[x as usize][y as usize].state,
+            CellState::Fluid | CellState::Outflow
+        )
+    }
+
+    /// Position of a tracer at `position` after moving with the current velocity field for
+    /// `duration` seconds, integrated with one RK4 step.
+    pub fn advect_point(&self, position: (f64, f64), duration: f64) -> (f64, f64) {
+        rk4_step(position, duration, |point| self.sample_velocity(point))
+    }
+
+    /// Streamline of the current velocity field through `seed` (metres), traced upstream and
+    /// downstream with RK4 steps of `step_length` metres, at most `max_points` points each way.
+    /// A streamline stops at walls, at the grid border and where the flow stagnates.
+    pub fn trace_streamline(&self, seed: (f64, f64), step_length: f64, max_points: usize) -> Vec<(f64, f64)> {
+        if !self.is_open_position(seed) {
+            return Vec::new();
+        }
+        let mut upstream = self.trace_streamline_half(seed, -step_length, max_points);
+        upstream.reverse();
+        upstream.push(seed);
+        upstream.extend(self.trace_streamline_half(seed, step_length, max_points));
+        upstream
+    }
+
+    fn trace_streamline_half(&self, seed: (f64, f64), step_length: f64, max_points: usize) -> Vec<(f64, f64)> {
+        // Integrating the unit direction makes every step `step_length` long whatever the speed
+        let direction = |point: (f64, f64)| {
+            let (u, v) = self.sample_velocity(point);
+            let speed = u.hypot(v);
+            (speed > STAGNATION_SPEED).then(|| (u / speed, v / speed))
+        };
+        let mut points = Vec::new();
+        let mut position = seed;
+        while points.len() < max_points {
+            match try_rk4_step(position, step_length, direction) {
+                Some(next_position) if self.is_open_position(next_position) => {
+                    position = next_position;
+                    points.push(position);
+                }
+                _ => break,
+            }
+        }
+        points
+    }
+}
+
+/// Classic fourth order Runge-Kutta step
+fn rk4_step(position: (f64, f64), step: f64, field: impl Fn((f64, f64)) -> (f64, f64)) -> (f64, f64) {
+    let offset = |k: (f64, f64), factor: f64| (position.0 + k.0 * step * factor, position.1 + k.1 * step * factor);
+    let k1 = field(position);
+    let k2 = field(offset(k1, 0.5));
+    let k3 = field(offset(k2, 0.5));
+    let k4 = field(offset(k3, 1.0));
+    rk4_combine(position, step, [k1, k2, k3, k4])
+}
+
+/// `rk4_step` for a field that may be undefined, `None` if it is anywhere along the way
+fn try_rk4_step(
+    position: (f64, f64),
+    step: f64,
+    field: impl Fn((f64, f64)) -> Option<(f64, f64)>,
+) -> Option<(f64, f64)> {
+    let offset = |k: (f64, f64), factor: f64| (position.0 + k.0 * step * factor, position.1 + k.1 * step * factor);
+    let k1 = field(position)?;
+    let k2 = field(offset(k1, 0.5))?;
+    let k3 = field(offset(k2, 0.5))?;
+    let k4 = field(offset(k3, 1.0))?;
+    Some(rk4_combine(position, step, [k1, k2, k3, k4]))
+}
+
+fn rk4_combine(position: (f64, f64), step: f64, [k1, k2, k3, k4]: [(f64, f64); 4]) -> (f64, f64) {
+    (
+        position.0 + step / 6.0 * (k1.0 + 2.0 * k2.0 + 2.0 * k3.0 + k4.0),
+        position.1 + step / 6.0 * (k1.1 + 2.0 * k2.1 + 2.0 * k3.1 + k4.1),
+    )
+}
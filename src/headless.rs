@@ -0,0 +1,392 @@
+use crate::colors::Colormap;
+use crate::contours::{extract_contours, spaced_levels, write_contours_csv};
+use crate::export::{write_npy_fields, VtkSeries};
+use crate::field_image::{draw_field, field_values, fit_to_fluid, ColorScale, PixelBuffer, RangeMode};
+use crate::fluid_engine::*;
+use crate::input_journal::InputJournal;
+use crate::logging::HEADLESS;
+use crate::metrics::MetricsSink;
+use crate::probes::{Probe, ProbeLog};
+use crate::recording::Recorder;
+use crate::scene_description::DisplayField;
+use crate::scenes;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const USAGE: &str = "Usage: fluid-demo --headless --scene <name|file.toml> [--steps <count>] [--output <directory>] [--output-every <steps>] [--metrics <file.csv|file.jsonl>] [--format csv|vtk|npy] [--resume <snapshot>] [--replay <journal>] [--record <directory|file.gif>] [--record-fps <fps>] [--record-field velocity-x|velocity-y|pressure|dye|speed|vorticity|divergence|q-criterion|stream-function] [--colormap viridis|inferno|coolwarm|rainbow] [--record-range <min>,<max>] [--record-log] [--contours <field>] [--contour-levels <level>,<level>,...] [--probe <x>,<y>]... [--line-probe <x0>,<y0>,<x1>,<y1>]...";
+/// Intervals the automatic range is split into when no contour levels are given
+const CONTOUR_INTERVALS: usize = 10;
+
+/// File format of the fields written every `output_every` steps
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FieldFormat {
+    /// `fields_<step>.csv`, one line per cell with the raw staggered velocities
+    Csv,
+    /// `fields_<step>.vti` frames and a `fields.pvd` collection for ParaView
+    Vtk,
+    /// `fields_<step>/` directories holding one `.npy` array per cell quantity
+    Npy,
+}
+
+/// Settings of a run without window, read from the command line.
+pub struct HeadlessOptions {
+    pub scene: String,
+    pub steps: usize,
+    pub output_directory: PathBuf,
+    /// Fields are written every `output_every` steps (0 disables field output)
+    pub output_every: usize,
+    pub format: FieldFormat,
+    /// Solver statistics file, `statistics.csv` in the output directory by default
+    pub metrics: Option<PathBuf>,
+    /// Snapshot the run starts from instead of the initial scene state
+    pub resume: Option<PathBuf>,
+    /// Input journal fed back to the scene, which defaults to the one of the journal
+    pub replay: Option<PathBuf>,
+    /// PNG frames directory or GIF file the rendered field is recorded to
+    pub record: Option<PathBuf>,
+    /// Frames per simulated second
+    pub record_fps: usize,
+    pub record_field: DisplayField,
+    /// Overrides the default colour map of `record_field`
+    pub colormap: Option<Colormap>,
+    /// Fixed range of the recorded frames instead of one fitted to every frame
+    pub record_range: Option<(f64, f64)>,
+    pub record_log: bool,
+    /// Field whose contours are written along with the fields
+    pub contour_field: Option<DisplayField>,
+    /// Contour levels, spaced over the range of every frame when empty
+    pub contour_levels: Vec<f64>,
+    /// Probes logged to `probes.csv` every step, positions in metres
+    pub probes: Vec<ProbeOption>,
+}
+
+/// Probe given on the command line, made into a `Probe` once the grid spacing is known
+pub enum ProbeOption {
+    Point((f64, f64)),
+    Line((f64, f64), (f64, f64)),
+}
+impl HeadlessOptions {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = HeadlessOptions {
+            scene: String::new(),
+            steps: 100,
+            output_directory: PathBuf::from("output"),
+            output_every: 10,
+            format: FieldFormat::Csv,
+            metrics: None,
+            resume: None,
+            replay: None,
+            record: None,
+            record_fps: 30,
+            record_field: DisplayField::Pressure,
+            colormap: None,
+            record_range: None,
+            record_log: false,
+            contour_field: None,
+            contour_levels: Vec::new(),
+            probes: Vec::new(),
+        };
+
+        let mut args_iter = args.iter();
+        while let Some(arg) = args_iter.next() {
+            let mut value = |name: &str| {
+                args_iter
+                    .next()
+                    .cloned()
+                    .ok_or(format!("Missing value for {}", name))
+            };
+            match arg.as_str() {
+                "--headless" => {}
+                "--scene" => options.scene = value(arg)?,
+                "--steps" => options.steps = parse_count(arg, &value(arg)?)?,
+                "--output" => options.output_directory = PathBuf::from(value(arg)?),
+                "--output-every" => options.output_every = parse_count(arg, &value(arg)?)?,
+                "--format" => {
+                    options.format = match value(arg)?.as_str() {
+                        "csv" => FieldFormat::Csv,
+                        "vtk" => FieldFormat::Vtk,
+                        "npy" => FieldFormat::Npy,
+                        format => return Err(format!("Unknown format: {}", format)),
+                    }
+                }
+                "--metrics" => options.metrics = Some(PathBuf::from(value(arg)?)),
+                "--resume" => options.resume = Some(PathBuf::from(value(arg)?)),
+                "--replay" => options.replay = Some(PathBuf::from(value(arg)?)),
+                "--record" => options.record = Some(PathBuf::from(value(arg)?)),
+                "--record-fps" => options.record_fps = parse_count(arg, &value(arg)?)?,
+                "--record-field" => options.record_field = parse_field(&value(arg)?)?,
+                "--colormap" => {
+                    let name = value(arg)?;
+                    options.colormap =
+                        Some(Colormap::from_name(&name).ok_or(format!("Unknown colormap: {}", name))?);
+                }
+                "--record-range" => {
+                    let range = value(arg)?;
+                    let bounds = range
+                        .split_once(',')
+                        .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)));
+                    options.record_range = match bounds {
+                        Some((min, max)) if min < max => Some((min, max)),
+                        _ => return Err(format!("Invalid value for {}: {}", arg, range)),
+                    };
+                }
+                "--record-log" => options.record_log = true,
+                "--contours" => options.contour_field = Some(parse_field(&value(arg)?)?),
+                "--contour-levels" => options.contour_levels = parse_list(arg, &value(arg)?, None)?,
+                "--probe" => {
+                    let coordinates = parse_list(arg, &value(arg)?, Some(2))?;
+                    options.probes.push(ProbeOption::Point((coordinates[0], coordinates[1])));
+                }
+                "--line-probe" => {
+                    let coordinates = parse_list(arg, &value(arg)?, Some(4))?;
+                    let (start, end) = ((coordinates[0], coordinates[1]), (coordinates[2], coordinates[3]));
+                    options.probes.push(ProbeOption::Line(start, end));
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        if options.scene.is_empty() && options.replay.is_none() {
+            return Err(format!(
+                "No scene given, available scenes: {} or a scene file",
+                scenes::SIMULATION_NAMES.join(", ")
+            ));
+        }
+        Ok(options)
+    }
+}
+
+/// Comma separated numbers, exactly `length` of them when given
+fn parse_list(name: &str, value: &str, length: Option<usize>) -> Result<Vec<f64>, String> {
+    let numbers: Vec<f64> = value
+        .split(',')
+        .map(|number| number.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid value for {}: {}", name, value))?;
+    match length {
+        Some(length) if numbers.len() != length => Err(format!("{} takes {} numbers, got {}", name, length, value)),
+        _ => Ok(numbers),
+    }
+}
+
+fn parse_field(name: &str) -> Result<DisplayField, String> {
+    match name {
+        "velocity-x" => Ok(DisplayField::VelocityX),
+        "velocity-y" => Ok(DisplayField::VelocityY),
+        "pressure" => Ok(DisplayField::Pressure),
+        "dye" => Ok(DisplayField::Dye),
+        "speed" => Ok(DisplayField::Speed),
+        "vorticity" => Ok(DisplayField::Vorticity),
+        "divergence" => Ok(DisplayField::Divergence),
+        "q-criterion" => Ok(DisplayField::QCriterion),
+        "stream-function" => Ok(DisplayField::StreamFunction),
+        field => Err(format!("Unknown field: {}", field)),
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", name, value))
+}
+
+/// Run a scene without opening a window, writing fields and per step statistics to disk.
+pub fn run(options: &HeadlessOptions) -> Result<(), String> {
+    let replayed_journal = match &options.replay {
+        Some(path) => Some(InputJournal::load(path)?),
+        None => None,
+    };
+    let scene = match &replayed_journal {
+        Some(journal) if options.scene.is_empty() => journal.scene.clone(),
+        _ => options.scene.clone(),
+    };
+    let mut simulation = scenes::simulation_by_name(&scene)?;
+    let mut journal = InputJournal::new(&scene);
+    if let Some(replayed_journal) = replayed_journal {
+        journal.start_replay(simulation.as_mut(), replayed_journal)?;
+    }
+    // Steps done before this run, outputs are numbered and timed from there
+    let mut first_step = 0;
+    if let Some(snapshot_path) = &options.resume {
+        let (fluid_domain, header) = FluidDomain::load_snapshot(snapshot_path)
+            .map_err(|e| format!("Unable to read {}: {}", snapshot_path.display(), e))?;
+        simulation.restore(fluid_domain, &header.scene_state)?;
+        first_step = header.step;
+    }
+    let resumed = options.resume.is_some();
+
+    fs::create_dir_all(&options.output_directory).map_err(|e| e.to_string())?;
+    let metrics_path = options
+        .metrics
+        .clone()
+        .unwrap_or_else(|| options.output_directory.join("statistics.csv"));
+    let metrics = if resumed {
+        MetricsSink::append(&metrics_path)
+    } else {
+        MetricsSink::create(&metrics_path)
+    };
+    let mut metrics = metrics
+        .map_err(|e| format!("Unable to write {}: {}", metrics_path.display(), e))?;
+
+    let mut vtk_series = VtkSeries::resume(&options.output_directory, "fields", first_step);
+    let mut recorder = match &options.record {
+        Some(path) => Some(
+            Recorder::new(path, options.record_fps as u32)
+                .map_err(|e| format!("Unable to record to {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    let spacing = simulation.fluid_domain().grid_spacing;
+    let probes: Vec<Probe> = options
+        .probes
+        .iter()
+        .map(|probe| match *probe {
+            ProbeOption::Point(position) => Probe::Point(position),
+            ProbeOption::Line(start, end) => Probe::line(start, end, spacing),
+        })
+        .collect();
+    let mut probe_log = if probes.is_empty() {
+        None
+    } else {
+        let path = options.output_directory.join("probes.csv");
+        let probe_log = if resumed { ProbeLog::append(&path) } else { ProbeLog::create(&path) };
+        Some(probe_log.map_err(|e| e.to_string())?)
+    };
+    let mut frame_pixels = PixelBuffer::new(simulation.fluid_domain().grid_size());
+    let mut color_scale = ColorScale::for_field(options.record_field);
+    if let Some(colormap) = options.colormap {
+        color_scale.colormap = colormap;
+    }
+    if let Some(range) = options.record_range {
+        color_scale.range_mode = RangeMode::Locked;
+        color_scale.range = range;
+    }
+    color_scale.log_scale = options.record_log;
+    let run_start = Instant::now();
+    let last_step = first_step + options.steps;
+    for step in first_step + 1..=last_step {
+        let step_start = Instant::now();
+        journal.step(simulation.as_mut());
+        let step_duration = step_start.elapsed();
+        log::info!(
+            target: HEADLESS,
+            "step {}/{}: {:.3} ms",
+            step,
+            last_step,
+            step_duration.as_secs_f64() * 1000.0
+        );
+
+        let fluid_domain = simulation.fluid_domain();
+        metrics
+            .write(
+                step,
+                fluid_domain.timestep,
+                &fluid_domain.diagnostics(),
+                Some(step_duration.as_secs_f64()),
+            )
+            .map_err(|e| e.to_string())?;
+
+        if let Some(probe_log) = probe_log.as_mut() {
+            probe_log
+                .write_step(step, fluid_domain, probes.iter().enumerate())
+                .map_err(|e| e.to_string())?;
+        }
+
+        if let Some(recorder) = recorder.as_mut() {
+            draw_field(&mut frame_pixels, fluid_domain, options.record_field, &mut color_scale);
+            recorder
+                .capture(&frame_pixels, step as f64 * fluid_domain.timestep)
+                .map_err(|e| e.to_string())?;
+        }
+
+        if options.output_every != 0 && step % options.output_every == 0 {
+            match options.format {
+                FieldFormat::Csv => {
+                    let fields_path = options
+                        .output_directory
+                        .join(format!("fields_{:06}.csv", step));
+                    write_fields(fluid_domain, &fields_path).map_err(|e| e.to_string())?;
+                }
+                FieldFormat::Vtk => vtk_series
+                    .write_frame(fluid_domain, step, step as f64 * fluid_domain.timestep)
+                    .map_err(|e| e.to_string())?,
+                FieldFormat::Npy => {
+                    let fields_directory = options
+                        .output_directory
+                        .join(format!("fields_{:06}", step));
+                    write_npy_fields(fluid_domain, &fields_directory).map_err(|e| e.to_string())?;
+                }
+            }
+            if let Some(field) = options.contour_field {
+                let contours_path = options
+                    .output_directory
+                    .join(format!("contours_{:06}.csv", step));
+                write_field_contours(fluid_domain, field, &options.contour_levels, &contours_path)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    metrics.flush().map_err(|e| e.to_string())?;
+    if let Some(mut probe_log) = probe_log {
+        probe_log.flush().map_err(|e| e.to_string())?;
+    }
+    if let Some(recorder) = recorder {
+        recorder.finish().map_err(|e| e.to_string())?;
+    }
+    let header = SnapshotHeader {
+        step: last_step,
+        scene_state: simulation.scene_state(),
+    };
+    simulation
+        .fluid_domain()
+        .save_snapshot(&header, &options.output_directory.join("final.snapshot"))
+        .map_err(|e| e.to_string())?;
+
+    let total_duration = run_start.elapsed();
+    log::info!(
+        target: HEADLESS,
+        "{} steps in {:.3} s ({:.3} ms/step), output in {}",
+        options.steps,
+        total_duration.as_secs_f64(),
+        total_duration.as_secs_f64() * 1000.0 / options.steps.max(1) as f64,
+        options.output_directory.display()
+    );
+    Ok(())
+}
+
+/// Contours of `field` at `levels`, or spaced over the fluid range of `field` when empty
+fn write_field_contours(fluid_domain: &FluidDomain, field: DisplayField, levels: &[f64], path: &Path) -> std::io::Result<()> {
+    let values = field_values(fluid_domain, field);
+    let levels = if levels.is_empty() {
+        let mut color_scale = ColorScale::for_field(field);
+        fit_to_fluid(&mut color_scale, fluid_domain, &values);
+        spaced_levels(&color_scale, CONTOUR_INTERVALS)
+    } else {
+        levels.to_vec()
+    };
+    write_contours_csv(&extract_contours(fluid_domain, &values, &levels), path)
+}
+
+/// One line per cell, velocities are the raw staggered values (u on the left face, v on the top face).
+fn write_fields(fluid_domain: &FluidDomain, path: &Path) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "x_id,y_id,state,u,v,pressure,divergence")?;
+    for (x_id, column) in fluid_domain.fluid_grid.iter().enumerate() {
+        for (y_id, cell) in column.iter().enumerate() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                x_id,
+                y_id,
+                cell.state as u8,
+                cell.velocity.0,
+                cell.velocity.1,
+                cell.pressure,
+                cell.divergence
+            )?;
+        }
+    }
+    writer.flush()
+}
@@ -0,0 +1,373 @@
+use crate::fluid_engine::CellState;
+use crate::scenes::restore_snapshot;
+use crate::simulation::Simulation;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const JOURNAL_HEADER: &str = "# fluid-demo input journal v2";
+
+/// User action changing a simulation, as stored in a journal
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneInput {
+    Reset,
+    /// `V` in the advection scene
+    ToggleInflow,
+    /// `hash` is the `snapshot_hash` of the file restored, `None` until it was read
+    RestoreSnapshot {
+        path: PathBuf,
+        hash: Option<u64>,
+    },
+    /// Mouse drag: Gaussian velocity impulse, positions and radius in metres
+    Impulse {
+        position: (f64, f64),
+        velocity: (f64, f64),
+        radius: f64,
+    },
+    /// Wall editor stroke or undo: new state of every changed cell
+    SetCellStates(Vec<(usize, usize, CellState)>),
+}
+impl SceneInput {
+    /// Restore of whatever snapshot `path` holds when the input is applied
+    pub fn restore_snapshot(path: impl Into<PathBuf>) -> Self {
+        SceneInput::RestoreSnapshot {
+            path: path.into(),
+            hash: None,
+        }
+    }
+
+    fn to_journal_text(&self) -> String {
+        match self {
+            SceneInput::Reset => "reset".to_string(),
+            SceneInput::ToggleInflow => "toggle-inflow".to_string(),
+            SceneInput::RestoreSnapshot { path, hash } => match hash {
+                Some(hash) => format!("restore {:016x} {}", hash, path.display()),
+                None => format!("restore - {}", path.display()),
+            },
+            SceneInput::Impulse {
+                position,
+                velocity,
+                radius,
+            } => format!(
+                "impulse {} {} {} {} {}",
+                position.0, position.1, velocity.0, velocity.1, radius
+            ),
+            SceneInput::SetCellStates(cells) => {
+                let mut text = "cells".to_string();
+                for (x_id, y_id, state) in cells.iter() {
+                    text.push_str(&format!(" {},{},{}", x_id, y_id, *state as u8));
+                }
+                text
+            }
+        }
+    }
+
+    fn from_journal_text(text: &str) -> Result<Self, String> {
+        let (name, argument) = text.split_once(' ').unwrap_or((text, ""));
+        match name {
+            "reset" => Ok(SceneInput::Reset),
+            "toggle-inflow" => Ok(SceneInput::ToggleInflow),
+            "restore" => {
+                let (hash, path) = argument
+                    .split_once(' ')
+                    .ok_or(format!("Invalid restore: {}", argument))?;
+                let hash = match hash {
+                    "-" => None,
+                    _ => Some(u64::from_str_radix(hash, 16).map_err(|_| format!("Invalid snapshot hash: {}", hash))?),
+                };
+                Ok(SceneInput::RestoreSnapshot {
+                    path: PathBuf::from(path),
+                    hash,
+                })
+            }
+            "impulse" => {
+                let values = argument
+                    .split_whitespace()
+                    .map(|value| value.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("Invalid impulse: {}", argument))?;
+                let [x, y, u, v, radius] = values[..] else {
+                    return Err(format!("Invalid impulse: {}", argument));
+                };
+                Ok(SceneInput::Impulse {
+                    position: (x, y),
+                    velocity: (u, v),
+                    radius,
+                })
+            }
+            "cells" => {
+                let parse_cell = |cell: &str| {
+                    let values: Vec<&str> = cell.split(',').collect();
+                    let [x_id, y_id, state] = values[..] else {
+                        return None;
+                    };
+                    Some((
+                        x_id.parse().ok()?,
+                        y_id.parse().ok()?,
+                        CellState::from_id(state.parse().ok()?)?,
+                    ))
+                };
+                argument
+                    .split_whitespace()
+                    .map(|cell| parse_cell(cell).ok_or(format!("Invalid cell: {}", cell)))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(SceneInput::SetCellStates)
+            }
+            _ => Err(format!("Unknown input: {}", text)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JournalEntry {
+    /// Number of steps done before the input was applied
+    pub step: usize,
+    pub input: SceneInput,
+}
+
+/// Steps a simulation while logging every input applied to it, or feeding back the inputs
+/// of a previous run so it is reproduced step by step.
+pub struct InputJournal {
+    /// Scene name or scene file, as given to `--scene`
+    pub scene: String,
+    pub entries: Vec<JournalEntry>,
+    step: usize,
+    /// Entries to replay, with the index of the next one to apply
+    replay: Option<(Vec<JournalEntry>, usize)>,
+    /// Bumped every time the domain is replaced: reset, snapshot restore or replay start
+    domain_generation: usize,
+}
+impl InputJournal {
+    pub fn new(scene: &str) -> Self {
+        InputJournal {
+            scene: scene.to_string(),
+            entries: Vec::new(),
+            step: 0,
+            replay: None,
+            domain_generation: 0,
+        }
+    }
+
+    /// Steps done since the journal was created or the replay started
+    pub fn steps(&self) -> usize {
+        self.step
+    }
+
+    /// Changes whenever the domain is replaced, so state recorded against the previous one
+    /// (such as wall editor undo entries) can be dropped
+    pub fn domain_generation(&self) -> usize {
+        self.domain_generation
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Apply and log a live input. Live inputs are ignored while replaying.
+    pub fn apply(&mut self, simulation: &mut dyn Simulation, input: SceneInput) {
+        if self.is_replaying() {
+            return;
+        }
+        self.record_and_apply(simulation, input);
+    }
+
+    /// Apply the replayed inputs due at this step, then advance the simulation.
+    pub fn step(&mut self, simulation: &mut dyn Simulation) {
+        while let Some((entries, next_entry)) = self.replay.as_mut() {
+            let Some(entry) = entries.get(*next_entry).filter(|entry| entry.step <= self.step) else {
+                break;
+            };
+            let input = entry.input.clone();
+            *next_entry += 1;
+            self.record_and_apply(simulation, input);
+        }
+        if let Some((entries, next_entry)) = &self.replay {
+            if *next_entry >= entries.len() {
+                self.replay = None;
+            }
+        }
+
+        simulation.step();
+        self.step += 1;
+    }
+
+    /// Restart `simulation` from its initial state and feed it the inputs of `journal`.
+    /// Refused when the journal was recorded in another scene, or when a snapshot it restores
+    /// changed since it was recorded.
+    pub fn start_replay(&mut self, simulation: &mut dyn Simulation, journal: InputJournal) -> Result<(), String> {
+        if journal.scene != self.scene {
+            return Err(format!(
+                "the journal was recorded in the {} scene, not {}",
+                journal.scene, self.scene
+            ));
+        }
+        for entry in journal.entries.iter() {
+            if let SceneInput::RestoreSnapshot { path, hash: Some(hash) } = &entry.input {
+                let bytes = std::fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+                if snapshot_hash(&bytes) != *hash {
+                    return Err(format!("{} changed since the journal was recorded", path.display()));
+                }
+            }
+        }
+        simulation.reset();
+        self.domain_generation += 1;
+        self.entries.clear();
+        self.step = 0;
+        self.replay = Some((journal.entries, 0));
+        Ok(())
+    }
+
+    fn record_and_apply(&mut self, simulation: &mut dyn Simulation, mut input: SceneInput) {
+        match &mut input {
+            SceneInput::Reset => {
+                simulation.reset();
+                self.domain_generation += 1;
+            }
+            SceneInput::RestoreSnapshot { path, hash } => {
+                // Logged with the hash of the file read, so a replay can tell if it changed.
+                // A failed restore left the domain as it was and is not logged.
+                let Some(restored_hash) = restore_snapshot(simulation, path, *hash) else {
+                    return;
+                };
+                *hash = Some(restored_hash);
+                self.domain_generation += 1;
+            }
+            SceneInput::Impulse {
+                position,
+                velocity,
+                radius,
+            } => simulation.apply_impulse(*position, *velocity, *radius),
+            SceneInput::SetCellStates(cells) => {
+                let fluid_domain = simulation.fluid_domain_mut();
+                let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+                for &(x_id, y_id, state) in cells.iter() {
+                    if x_id < grid_size_x && y_id < grid_size_y {
+                        fluid_domain.set_cell_state(x_id, y_id, state);
+                    }
+                }
+            }
+            _ => simulation.apply_input(&input),
+        }
+        self.entries.push(JournalEntry {
+            step: self.step,
+            input,
+        });
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", JOURNAL_HEADER)?;
+        writeln!(writer, "scene {}", self.scene)?;
+        for entry in self.entries.iter() {
+            writeln!(writer, "{} {}", entry.step, entry.input.to_journal_text())?;
+        }
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let mut lines = text.lines();
+        if lines.next() != Some(JOURNAL_HEADER) {
+            return Err(format!("Invalid journal {}: missing header {}", path.display(), JOURNAL_HEADER));
+        }
+        let scene = lines
+            .next()
+            .and_then(|line| line.strip_prefix("scene "))
+            .ok_or(format!("Invalid journal {}: missing scene", path.display()))?;
+
+        let mut journal = InputJournal::new(scene);
+        for (line_id, line) in lines.enumerate() {
+            let invalid_line = |error: String| {
+                format!("Invalid journal {} line {}: {}", path.display(), line_id + 3, error)
+            };
+            let (step, input) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid_line(line.to_string()))?;
+            journal.entries.push(JournalEntry {
+                step: step.parse().map_err(|_| invalid_line(step.to_string()))?,
+                input: SceneInput::from_journal_text(input).map_err(invalid_line)?,
+            });
+        }
+        Ok(journal)
+    }
+}
+
+/// 64 bits FNV-1a hash of a snapshot file, stable across runs and builds
+pub fn snapshot_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_engine::SnapshotHeader;
+    use crate::scenes::AdvectionSimulation;
+
+    const STEPS: usize = 20;
+
+    fn domain_hash(simulation: &dyn Simulation) -> u64 {
+        let mut bytes = Vec::new();
+        simulation
+            .fluid_domain()
+            .write_snapshot(&SnapshotHeader::default(), &mut bytes)
+            .unwrap();
+        snapshot_hash(&bytes)
+    }
+
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fluid-demo-{}-{}", std::process::id(), name))
+    }
+
+    /// Live run of the advection scene with inflow toggles and a failed restore
+    fn record_session(simulation: &mut dyn Simulation) -> InputJournal {
+        let mut journal = InputJournal::new("advection");
+        for step in 0..STEPS {
+            match step {
+                3 | 12 => journal.apply(simulation, SceneInput::ToggleInflow),
+                8 => journal.apply(simulation, SceneInput::restore_snapshot(temporary_path("missing.snapshot"))),
+                _ => {}
+            }
+            journal.step(simulation);
+        }
+        journal
+    }
+
+    #[test]
+    fn failed_restore_is_not_journaled() {
+        let journal = record_session(&mut AdvectionSimulation::new());
+        assert_eq!(journal.entries.len(), 2);
+        assert!(journal
+            .entries
+            .iter()
+            .all(|entry| !matches!(entry.input, SceneInput::RestoreSnapshot { .. })));
+    }
+
+    #[test]
+    fn saved_journal_replays_the_same_run() {
+        let mut simulation = AdvectionSimulation::new();
+        let journal = record_session(&mut simulation);
+        let path = temporary_path("round-trip.journal");
+        journal.save(&path).unwrap();
+        let loaded_journal = InputJournal::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut replayed_simulation = AdvectionSimulation::new();
+        let mut replay = InputJournal::new("advection");
+        replay.start_replay(&mut replayed_simulation, loaded_journal.unwrap()).unwrap();
+        for _ in 0..STEPS {
+            replay.step(&mut replayed_simulation);
+        }
+        assert!(!replay.is_replaying());
+        assert_eq!(domain_hash(&replayed_simulation), domain_hash(&simulation));
+    }
+
+    #[test]
+    fn journal_of_another_scene_is_refused() {
+        let mut replay = InputJournal::new("advection");
+        let result = replay.start_replay(&mut AdvectionSimulation::new(), InputJournal::new("dam-break"));
+        assert!(result.is_err());
+    }
+}
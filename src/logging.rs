@@ -0,0 +1,129 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use raylib::consts::TraceLogLevel;
+
+// Targets of the subsystems, given to the `log` macros
+/// Pressure solve and other engine internals
+pub const SOLVER: &str = "solver";
+/// Scene loading, snapshots and input journals
+pub const SCENE: &str = "scene";
+/// Field colouring and overlays
+pub const RENDER: &str = "render";
+/// CSV and `.npy` files written on request
+pub const EXPORT: &str = "export";
+/// PNG and GIF recordings
+pub const RECORDING: &str = "recording";
+/// Runs without window
+pub const HEADLESS: &str = "headless";
+/// Messages of raylib itself
+pub const RAYLIB: &str = "raylib";
+
+/// Environment variable holding the filter when `--log` is not given
+pub const LOG_ENV_VARIABLE: &str = "FLUID_DEMO_LOG";
+/// Everything from info up in debug builds, only errors in release builds
+#[cfg(debug_assertions)]
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+#[cfg(not(debug_assertions))]
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Error;
+/// The headless runner reports its progress whatever the build, unless the filter says otherwise
+const DEFAULT_TARGET_LEVELS: [(&str, LevelFilter); 1] = [(HEADLESS, LevelFilter::Info)];
+
+/// Writes log records to stderr, keeping stdout for data, with a level per target.
+///
+/// Filters read like `warn,solver=trace,export=info`: a default level and levels of given
+/// targets, `off` silencing one.
+struct ConsoleLogger {
+    default_level: LevelFilter,
+    target_levels: Vec<(String, LevelFilter)>,
+}
+impl ConsoleLogger {
+    fn parse(filter: &str) -> Result<Self, String> {
+        let mut logger = ConsoleLogger {
+            default_level: DEFAULT_LEVEL,
+            target_levels: DEFAULT_TARGET_LEVELS
+                .iter()
+                .map(|(target, level)| (target.to_string(), *level))
+                .collect(),
+        };
+        for directive in filter.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            let parse_level = |level: &str| {
+                level
+                    .parse::<LevelFilter>()
+                    .map_err(|_| format!("Invalid log level: {}", level))
+            };
+            match directive.split_once('=') {
+                Some((target, level)) => logger.target_levels.push((target.to_string(), parse_level(level)?)),
+                None => logger.default_level = parse_level(directive)?,
+            }
+        }
+        Ok(logger)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.target_levels
+            .iter()
+            .rev()
+            .find(|(filtered_target, _)| filtered_target == target)
+            .map_or(self.default_level, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.target_levels
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default_level, Ord::max)
+    }
+}
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the logger with `filter`, or the one of `FLUID_DEMO_LOG` when `None`
+pub fn init(filter: Option<&str>) -> Result<(), String> {
+    let filter = match filter {
+        Some(filter) => filter.to_string(),
+        None => std::env::var(LOG_ENV_VARIABLE).unwrap_or_default(),
+    };
+    let logger = ConsoleLogger::parse(&filter)?;
+    log::set_max_level(logger.max_level());
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| e.to_string())
+}
+
+/// Lowest raylib level let through to the `raylib` target, for `RaylibBuilder::log_level`
+/// so the messages of the window creation are filtered as well
+pub fn raylib_level() -> TraceLogLevel {
+    let metadata = |level| Metadata::builder().target(RAYLIB).level(level).build();
+    match [Level::Trace, Level::Debug, Level::Info, Level::Warn, Level::Error]
+        .into_iter()
+        .find(|level| log::logger().enabled(&metadata(*level)))
+    {
+        Some(Level::Trace) => TraceLogLevel::LOG_TRACE,
+        Some(Level::Debug) => TraceLogLevel::LOG_DEBUG,
+        Some(Level::Info) => TraceLogLevel::LOG_INFO,
+        Some(Level::Warn) => TraceLogLevel::LOG_WARNING,
+        Some(Level::Error) => TraceLogLevel::LOG_ERROR,
+        None => TraceLogLevel::LOG_NONE,
+    }
+}
+
+/// raylib trace log callback, forwarding to the `raylib` target
+pub fn forward_raylib_message(level: TraceLogLevel, text: &str) {
+    let level = match level {
+        TraceLogLevel::LOG_TRACE | TraceLogLevel::LOG_ALL => Level::Trace,
+        TraceLogLevel::LOG_DEBUG => Level::Debug,
+        TraceLogLevel::LOG_INFO => Level::Info,
+        TraceLogLevel::LOG_WARNING => Level::Warn,
+        TraceLogLevel::LOG_ERROR | TraceLogLevel::LOG_FATAL => Level::Error,
+        TraceLogLevel::LOG_NONE => return,
+    };
+    log::log!(target: RAYLIB, level, "{}", text);
+}
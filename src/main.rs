@@ -5,17 +5,77 @@ use std::time::Instant;
 
 mod scenes;
 mod fluid_engine;
+mod simulation;
+mod headless;
+mod scene_description;
+mod domain_mask;
+mod export;
+mod field_image;
+mod field_renderer;
+mod recording;
+mod input_journal;
+mod wall_editor;
+mod view_camera;
+mod quiver;
+mod flow_lines;
+mod contours;
+mod plots;
+mod probes;
+mod logging;
+mod metrics;
 
 mod colors;
 use colors::*;
 
 fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let log_filter = match take_option(&mut args, "--log") {
+        Ok(log_filter) => log_filter,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    if let Err(error) = logging::init(log_filter.as_deref()) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    if args.iter().any(|arg| arg == "--headless") {
+        let result = headless::HeadlessOptions::from_args(&args).and_then(|options| headless::run(&options));
+        if let Err(error) = result {
+            eprintln!("{}\n{}", error, headless::USAGE);
+            std::process::exit(1);
+        }
+        return;
+    }
+    // Statistics of the running scene after every step
+    let mut metrics = match take_option(&mut args, "--metrics") {
+        Ok(Some(path)) => match metrics::MetricsSink::create(std::path::Path::new(&path)) {
+            Ok(sink) => Some(sink),
+            Err(error) => {
+                log::error!(target: logging::EXPORT, "Unable to write {}: {}", path, error);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
     let (mut rl_handle, rl_thread) = raylib::init()
         .size(640, 480)
         .resizable()
+        .msaa_4x()
         .title("Fluid demo")
+        .log_level(logging::raylib_level())
         .build();
-    rl_handle.set_target_fps(60);
+    if let Err(error) = rl_handle.set_trace_log_callback(logging::forward_raylib_message) {
+        log::warn!(target: logging::RAYLIB, "raylib messages are not forwarded: {}", error);
+    }
+    const WINDOW_FPS: u32 = 60;
+    rl_handle.set_target_fps(WINDOW_FPS);
     rl_handle.set_exit_key(None);
     rl_handle.gui_set_style(
         GuiControl::DEFAULT,
@@ -34,20 +94,72 @@ fn main() {
     let mut scenes: Vec<Box<dyn scenes::Scene>> = vec![
         Box::<scenes::BasicFuildScene>::new(scenes::BasicFuildScene::new(&mut rl_handle, &rl_thread)),
         Box::<scenes::AdvectionFuildScene>::new(scenes::AdvectionFuildScene::new(&mut rl_handle, &rl_thread)),
+        Box::<scenes::DamBreakScene>::new(scenes::DamBreakScene::new(&mut rl_handle, &rl_thread)),
     ];
+    scenes.extend(scenes::load_scene_files(
+        &mut rl_handle,
+        &rl_thread,
+        std::path::Path::new(scenes::SCENES_DIRECTORY),
+    ));
     let mut current_scene: Option<usize> = None;
 
+    let mut recorder: Option<recording::Recorder> = None;
+
     const TITLE_FONT_SIZE: i32 = 80;
+    const RECORDING_FPS: u32 = 30;
+    let mut target_fps = WINDOW_FPS;
     let mut clock_divider = 0;
     let mut current_draw_time_text = String::new();
     // let mut scene_to_load = None;
     while !rl_handle.window_should_close() {
         if let Some(scene_id) = current_scene {
-            scenes[scene_id].update(&mut rl_handle);
+            let previous_steps = scenes[scene_id].steps();
+            scenes[scene_id].update(&mut rl_handle, &rl_thread);
+            let steps = scenes[scene_id].steps();
+            let diagnostics = scenes[scene_id].diagnostics().filter(|_| steps != previous_steps);
+            if let (Some(sink), Some(diagnostics)) = (metrics.as_mut(), diagnostics) {
+                let timestep = scenes[scene_id].fluid_domain().timestep;
+                if let Err(error) = sink.write(steps, timestep, diagnostics, None).and_then(|()| sink.flush()) {
+                    log::error!(target: logging::EXPORT, "Unable to write the metrics, writing stopped: {}", error);
+                    metrics = None;
+                }
+            }
+
+            // F9 records numbered PNG files, F10 an animated GIF
+            for (key, extension) in [(KeyboardKey::KEY_F9, ""), (KeyboardKey::KEY_F10, ".gif")] {
+                if !rl_handle.is_key_pressed(key) {
+                    continue;
+                }
+                if let Some(running_recorder) = recorder.take() {
+                    stop_recording(running_recorder);
+                } else {
+                    let path = recording::next_free_path("recordings/recording", extension);
+                    match recording::Recorder::new(&path, RECORDING_FPS) {
+                        Ok(new_recorder) => recorder = Some(new_recorder),
+                        Err(error) => log::error!(target: logging::RECORDING, "Unable to record to {}: {}", path.display(), error),
+                    }
+                }
+            }
+            if let Some(running_recorder) = recorder.as_mut() {
+                let time = rl_handle.get_time();
+                if let Err(error) = running_recorder.capture(scenes[scene_id].render_pixels(), time) {
+                    log::error!(target: logging::RECORDING, "Recording stopped: {}", error);
+                    recorder = None;
+                }
+            }
         }
 
         if rl_handle.is_key_pressed(KeyboardKey::KEY_ESCAPE) {
             current_scene = None;
+            if let Some(running_recorder) = recorder.take() {
+                stop_recording(running_recorder);
+            }
+        }
+        // Recordings play at RECORDING_FPS, so the window runs at that rate while recording
+        let wanted_fps = if recorder.is_some() { RECORDING_FPS } else { WINDOW_FPS };
+        if wanted_fps != target_fps {
+            rl_handle.set_target_fps(wanted_fps);
+            target_fps = wanted_fps;
         }
         let help_page_requested = rl_handle.is_key_down(KeyboardKey::KEY_H);
 
@@ -79,8 +191,25 @@ fn main() {
                     COLOR_LIGHT,
                 );
 
+                if let Some(running_recorder) = recorder.as_ref() {
+                    let recording_text = format!("REC {}", running_recorder.frame_count());
+                    let text_x = screen_width - rl_draw_handle.measure_text(recording_text.as_str(), 18) - 30;
+                    rl_draw_handle.draw_circle(text_x - 14, 54, 7.0, COLOR_RED);
+                    rl_draw_handle.draw_text(recording_text.as_str(), text_x, 45, 18, COLOR_LIGHT);
+                }
+
                 if help_page_requested {
-                    draw_help_page(&mut rl_draw_handle, scenes[scene_id].help_text());
+                    let mut help_text = scenes[scene_id].help_text();
+                    help_text.push("F9: start / stop recording PNG frames to recordings/");
+                    help_text.push("F10: start / stop recording a GIF to recordings/");
+                    help_text.push("Mouse wheel: zoom, middle drag: pan, F: fit to window");
+                    help_text.push("B: toggle nearest / bilinear filtering");
+                    help_text.push("C: next colour map, O: automatic / symmetric / locked range, G: toggle log scale");
+                    help_text.push("I: cycle isolines, U: toggle GPU / CPU field colouring");
+                    help_text.push("Y: hide / show / label contours, J: contour count, Shift + Y: export contours as CSV");
+                    help_text.push("D: toggle the plots of the energy, enstrophy, divergence, volume and CFL diagnostics");
+                    help_text.push("Ctrl + click: point probe, Ctrl + drag: line probe, Ctrl + right click: remove a probe");
+                    draw_help_page(&mut rl_draw_handle, help_text);
                 }
             } else {
                 rl_draw_handle.draw_text(
@@ -127,6 +256,27 @@ fn main() {
     }
 }
 
+/// Remove `name` and its value from `args`, the last one being kept when given several times
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let mut value = None;
+    while let Some(index) = args.iter().position(|arg| arg == name) {
+        if index + 1 >= args.len() {
+            return Err(format!("Missing value for {}", name));
+        }
+        value = Some(args.remove(index + 1));
+        args.remove(index);
+    }
+    Ok(value)
+}
+
+fn stop_recording(recorder: recording::Recorder) {
+    let path = recorder.path().to_path_buf();
+    match recorder.finish() {
+        Ok(frame_count) => log::info!(target: logging::RECORDING, "{} frames recorded to {}", frame_count, path.display()),
+        Err(error) => log::error!(target: logging::RECORDING, "Unable to finish {}: {}", path.display(), error),
+    }
+}
+
 fn draw_background(rl_draw_handle: &mut RaylibDrawHandle, tile_texture: &Texture2D) {
     let screen_width = rl_draw_handle.get_screen_width();
     let screen_height = rl_draw_handle.get_screen_height();
@@ -153,8 +303,18 @@ pub fn draw_help_page(rl_draw_handle: &mut RaylibDrawHandle, text_to_display: Ve
     let screen_width = rl_draw_handle.get_screen_width();
     let screen_height = rl_draw_handle.get_screen_height();
 
-    let panel_width: i32 = 600;
-    let panel_height: i32 = 10 + 32 + 10 + 20 * (text_to_display.len() as i32) + 10;
+    // Lines are tightened, down to a 10 px font, until they all fit in the window height
+    const TITLE_HEIGHT: i32 = 10 + 32 + 10;
+    let line_count = text_to_display.len().max(1) as i32;
+    let line_height = ((screen_height - 20 - TITLE_HEIGHT - 10) / line_count).clamp(12, 20);
+    let font_size = line_height - 2;
+    let text_width = text_to_display
+        .iter()
+        .map(|text| rl_draw_handle.measure_text(text, font_size))
+        .max()
+        .unwrap_or(0);
+    let panel_width: i32 = (text_width + 20).max(600);
+    let panel_height: i32 = TITLE_HEIGHT + line_height * line_count + 10;
     let panel_x = (screen_width - panel_width) / 2;
     let panel_y = (screen_height - panel_height) / 2;
     rl_draw_handle.draw_rectangle_rounded(
@@ -180,8 +340,8 @@ pub fn draw_help_page(rl_draw_handle: &mut RaylibDrawHandle, text_to_display: Ve
         rl_draw_handle.draw_text(
             text,
             panel_x + 10,
-            panel_y + 52 + (i as i32) * 20,
-            18,
+            panel_y + TITLE_HEIGHT + (i as i32) * line_height,
+            font_size,
             COLOR_LIGHT,
         );
     }
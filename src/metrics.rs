@@ -0,0 +1,168 @@
+use crate::fluid_engine::Diagnostics;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const COLUMNS: [&str; 13] = [
+    "step",
+    "time",
+    "step_ms",
+    "kinetic_energy",
+    "max_speed",
+    "enstrophy",
+    "divergence_before_max",
+    "divergence_before_rms",
+    "divergence_after_max",
+    "divergence_after_rms",
+    "solver_iterations",
+    "fluid_volume",
+    "cfl",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricsFormat {
+    /// Header line, then one line of comma separated values per step
+    Csv,
+    /// One JSON object per step and line
+    JsonLines,
+}
+
+/// Solver statistics written after every step: the `Diagnostics` of the fluid domain with the
+/// step index, simulated time and step duration
+pub struct MetricsSink {
+    format: MetricsFormat,
+    writer: BufWriter<File>,
+}
+impl MetricsSink {
+    /// JSON lines for `.jsonl` and `.json` files, CSV otherwise
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(path, File::create(path)?)
+    }
+
+    /// Sink continuing `path`, for runs resumed from a snapshot
+    pub fn append(path: &Path) -> io::Result<Self> {
+        Self::new(path, OpenOptions::new().create(true).append(true).open(path)?)
+    }
+
+    fn new(path: &Path, file: File) -> io::Result<Self> {
+        let format = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl" | "json") => MetricsFormat::JsonLines,
+            _ => MetricsFormat::Csv,
+        };
+        let is_empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if format == MetricsFormat::Csv && is_empty {
+            writeln!(writer, "{}", COLUMNS.join(","))?;
+        }
+        Ok(MetricsSink { format, writer })
+    }
+
+    /// `diagnostics` after `step` steps of `timestep` seconds, `step_duration` being unknown when
+    /// `None`
+    pub fn write(
+        &mut self,
+        step: usize,
+        timestep: f64,
+        diagnostics: &Diagnostics,
+        step_duration: Option<f64>,
+    ) -> io::Result<()> {
+        let projection = &diagnostics.projection;
+        let values = [
+            step as f64,
+            step as f64 * timestep,
+            step_duration.map_or(f64::NAN, |duration| duration * 1000.0),
+            diagnostics.kinetic_energy,
+            diagnostics.max_speed,
+            diagnostics.enstrophy,
+            projection.divergence_before.max,
+            projection.divergence_before.rms,
+            projection.divergence_after.max,
+            projection.divergence_after.rms,
+            projection.iterations as f64,
+            diagnostics.fluid_volume,
+            diagnostics.cfl,
+        ];
+        match self.format {
+            MetricsFormat::Csv => {
+                // Non finite values are left empty, as in the JSON lines
+                let fields: Vec<String> = values
+                    .iter()
+                    .map(|value| if value.is_finite() { value.to_string() } else { String::new() })
+                    .collect();
+                writeln!(self.writer, "{}", fields.join(","))
+            }
+            MetricsFormat::JsonLines => {
+                let fields: Vec<String> = COLUMNS
+                    .iter()
+                    .zip(values)
+                    .map(|(name, value)| {
+                        // JSON has no NaN or infinity
+                        let value = if value.is_finite() { value.to_string() } else { "null".to_string() };
+                        format!("\"{}\":{}", name, value)
+                    })
+                    .collect();
+                writeln!(self.writer, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid_engine::ProjectionStats;
+
+    /// Lines written for two steps, the second one with an infinite CFL number
+    fn written_lines(extension: &str) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!("fluid-demo-{}-metrics.{}", std::process::id(), extension));
+        let diagnostics = Diagnostics {
+            kinetic_energy: 2.5,
+            enstrophy: 0.5,
+            max_speed: 1.0,
+            projection: ProjectionStats::default(),
+            fluid_volume: 100.0,
+            cfl: 0.25,
+        };
+        let mut sink = MetricsSink::create(&path).unwrap();
+        sink.write(1, 0.1, &diagnostics, Some(0.002)).unwrap();
+        let diverged = Diagnostics {
+            cfl: f64::INFINITY,
+            ..diagnostics
+        };
+        sink.write(2, 0.1, &diverged, None).unwrap();
+        sink.flush().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn csv_has_a_header_and_blanks_non_finite_values() {
+        let lines = written_lines("csv");
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], COLUMNS.join(","));
+        let first: Vec<&str> = lines[1].split(',').collect();
+        assert_eq!(first.len(), COLUMNS.len());
+        assert_eq!(first[0], "1");
+        assert_eq!(first[2], "2");
+        assert_eq!(first[3], "2.5");
+        assert_eq!(first[12], "0.25");
+        let second: Vec<&str> = lines[2].split(',').collect();
+        assert_eq!(second[2], "");
+        assert_eq!(second[12], "");
+    }
+
+    #[test]
+    fn json_lines_hold_one_object_per_step_with_nulls() {
+        let lines = written_lines("jsonl");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("{\"step\":1,\"time\":0.1,\"step_ms\":2,"));
+        assert!(lines[0].ends_with("\"cfl\":0.25}"));
+        assert!(lines[1].contains("\"step_ms\":null,"));
+        assert!(lines[1].ends_with("\"cfl\":null}"));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::colors::*;
+use crate::field_image::format_value;
+use crate::fluid_engine::Diagnostics;
+use raylib::prelude::*;
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+
+/// Steps kept in every plot
+const HISTORY_LENGTH: usize = 600;
+const PANEL_WIDTH: f32 = 340.0;
+const PANEL_MARGIN: f32 = 10.0;
+const ROW_HEIGHT: f32 = 26.0;
+const PLOT_HEIGHT: f32 = 60.0;
+const AXIS_FONT_SIZE: i32 = 12;
+
+/// Global quantity of the fluid domain, recorded after every step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlotQuantity {
+    KineticEnergy,
+    Enstrophy,
+    MaxDivergenceBefore,
+    MaxDivergenceAfter,
+    RmsDivergenceAfter,
+    FluidVolume,
+    Cfl,
+}
+pub const PLOT_QUANTITIES: [PlotQuantity; 7] = [
+    PlotQuantity::KineticEnergy,
+    PlotQuantity::Enstrophy,
+    PlotQuantity::MaxDivergenceBefore,
+    PlotQuantity::MaxDivergenceAfter,
+    PlotQuantity::RmsDivergenceAfter,
+    PlotQuantity::FluidVolume,
+    PlotQuantity::Cfl,
+];
+impl PlotQuantity {
+    pub fn label(self) -> &'static CStr {
+        match self {
+            PlotQuantity::KineticEnergy => c"Kinetic energy (J/m)",
+            PlotQuantity::Enstrophy => c"Enstrophy (m^2/s^2)",
+            PlotQuantity::MaxDivergenceBefore => c"Max divergence before projection (1/s)",
+            PlotQuantity::MaxDivergenceAfter => c"Max divergence after projection (1/s)",
+            PlotQuantity::RmsDivergenceAfter => c"RMS divergence after projection (1/s)",
+            PlotQuantity::FluidVolume => c"Fluid volume (m^2)",
+            PlotQuantity::Cfl => c"CFL number",
+        }
+    }
+
+    pub fn value(self, diagnostics: &Diagnostics) -> f64 {
+        let projection = &diagnostics.projection;
+        match self {
+            PlotQuantity::KineticEnergy => diagnostics.kinetic_energy,
+            PlotQuantity::Enstrophy => diagnostics.enstrophy,
+            PlotQuantity::MaxDivergenceBefore => projection.divergence_before.max,
+            PlotQuantity::MaxDivergenceAfter => projection.divergence_after.max,
+            PlotQuantity::RmsDivergenceAfter => projection.divergence_after.rms,
+            PlotQuantity::FluidVolume => diagnostics.fluid_volume,
+            PlotQuantity::Cfl => diagnostics.cfl,
+        }
+    }
+}
+
+/// What a plotted series records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SeriesSource {
+    Quantity(PlotQuantity),
+    /// Speed at the point probe of this id
+    ProbeSpeed(usize),
+}
+
+struct Series {
+    source: SeriesSource,
+    label: CString,
+    /// Plotted, otherwise only its checkbox is drawn
+    shown: bool,
+    /// Oldest first, at most `HISTORY_LENGTH` values
+    values: VecDeque<f64>,
+}
+
+/// Time series of the diagnostics of the fluid domain and of the speed at the point probes
+/// over the last `HISTORY_LENGTH` steps, drawn as stacked plots in the bottom left corner.
+/// Every series is recorded while the panel is hidden so its history is there when it opens;
+/// checkboxes pick the plotted ones.
+pub struct PlotPanel {
+    pub visible: bool,
+    series: Vec<Series>,
+}
+impl PlotPanel {
+    pub fn new() -> Self {
+        PlotPanel {
+            visible: false,
+            series: PLOT_QUANTITIES
+                .iter()
+                .map(|&quantity| Series {
+                    source: SeriesSource::Quantity(quantity),
+                    label: quantity.label().to_owned(),
+                    shown: quantity == PlotQuantity::KineticEnergy,
+                    values: VecDeque::with_capacity(HISTORY_LENGTH),
+                })
+                .collect(),
+        }
+    }
+
+    /// `D` toggles the panel
+    pub fn update(&mut self, rl_handle: &RaylibHandle) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_D) {
+            self.visible = !self.visible;
+        }
+    }
+
+    /// Append the current values, after every simulation step. `probe_speeds` holds the speed
+    /// at every point probe with its id: series are added for new probes and removed along
+    /// with their probe.
+    pub fn record(&mut self, diagnostics: &Diagnostics, probe_speeds: &[(usize, f64)]) {
+        self.series.retain(|series| match series.source {
+            SeriesSource::Quantity(_) => true,
+            SeriesSource::ProbeSpeed(probe_id) => probe_speeds.iter().any(|(id, _)| *id == probe_id),
+        });
+        for &(probe_id, _) in probe_speeds.iter() {
+            let source = SeriesSource::ProbeSpeed(probe_id);
+            if !self.series.iter().any(|series| series.source == source) {
+                self.series.push(Series {
+                    source,
+                    label: CString::new(format!("Probe {} speed (m/s)", probe_id)).unwrap(),
+                    shown: true,
+                    values: VecDeque::with_capacity(HISTORY_LENGTH),
+                });
+            }
+        }
+
+        for series in self.series.iter_mut() {
+            if series.values.len() == HISTORY_LENGTH {
+                series.values.pop_front();
+            }
+            let value = match series.source {
+                SeriesSource::Quantity(quantity) => quantity.value(diagnostics),
+                SeriesSource::ProbeSpeed(probe_id) => probe_speeds
+                    .iter()
+                    .find(|(id, _)| *id == probe_id)
+                    .map_or(f64::NAN, |(_, speed)| *speed),
+            };
+            series.values.push_back(value);
+        }
+    }
+
+    pub fn draw(&mut self, rl_handle: &mut RaylibDrawHandle) {
+        if !self.visible {
+            return;
+        }
+        let panel_height: f32 = self
+            .series
+            .iter()
+            .map(|series| ROW_HEIGHT + if series.shown { PLOT_HEIGHT + 6.0 } else { 0.0 })
+            .sum::<f32>()
+            + 2.0 * PANEL_MARGIN;
+        let panel_x = PANEL_MARGIN;
+        let panel_y = rl_handle.get_screen_height() as f32 - panel_height - PANEL_MARGIN;
+        rl_handle.draw_rectangle_rec(
+            Rectangle::new(panel_x, panel_y, PANEL_WIDTH, panel_height),
+            COLOR_LIGHT.alpha(0.85),
+        );
+
+        let (x, width) = (panel_x + PANEL_MARGIN, PANEL_WIDTH - 2.0 * PANEL_MARGIN);
+        let mut y = panel_y + PANEL_MARGIN;
+        for series in self.series.iter_mut() {
+            rl_handle.gui_check_box(
+                Rectangle::new(x, y + 4.0, 16.0, 16.0),
+                Some(series.label.as_c_str()),
+                &mut series.shown,
+            );
+            y += ROW_HEIGHT;
+            if series.shown {
+                draw_plot(rl_handle, Rectangle::new(x, y, width, PLOT_HEIGHT), &series.values);
+                y += PLOT_HEIGHT + 6.0;
+            }
+        }
+    }
+}
+
+/// Line of `values` over the whole width of `bounds`, scaled between their extrema which are
+/// written on the left along with the last value on the right
+fn draw_plot(rl_handle: &mut RaylibDrawHandle, bounds: Rectangle, values: &VecDeque<f64>) {
+    rl_handle.draw_rectangle_rec(bounds, COLOR_WHITE);
+    rl_handle.draw_rectangle_lines_ex(bounds, 1.0, COLOR_DARK);
+    let Some(&last_value) = values.back() else {
+        return;
+    };
+    let finite_values = values.iter().filter(|value| value.is_finite());
+    let min_value = finite_values.clone().fold(f64::MAX, |min, &value| min.min(value));
+    let max_value = finite_values.fold(f64::MIN, |max, &value| max.max(value));
+    if min_value > max_value {
+        return;
+    }
+
+    let value_span = if max_value > min_value { max_value - min_value } else { 1.0 };
+    let to_screen = |index: usize, value: f64| {
+        let level = if max_value > min_value { (value - min_value) / value_span } else { 0.5 };
+        Vector2::new(
+            bounds.x + bounds.width * index as f32 / (HISTORY_LENGTH - 1) as f32,
+            bounds.y + bounds.height * (1.0 - level as f32),
+        )
+    };
+    let mut previous_point: Option<Vector2> = None;
+    for (index, &value) in values.iter().enumerate() {
+        if !value.is_finite() {
+            previous_point = None;
+            continue;
+        }
+        let point = to_screen(index, value);
+        if let Some(previous_point) = previous_point {
+            rl_handle.draw_line_v(previous_point, point, COLOR_BLUE);
+        }
+        previous_point = Some(point);
+    }
+
+    let (text_x, text_bottom) = (bounds.x as i32 + 3, (bounds.y + bounds.height) as i32 - AXIS_FONT_SIZE - 2);
+    rl_handle.draw_text(&format_value(max_value), text_x, bounds.y as i32 + 2, AXIS_FONT_SIZE, COLOR_DARK);
+    rl_handle.draw_text(&format_value(min_value), text_x, text_bottom, AXIS_FONT_SIZE, COLOR_DARK);
+    let last_label = format_value(last_value);
+    let last_x = (bounds.x + bounds.width) as i32 - rl_handle.measure_text(&last_label, AXIS_FONT_SIZE) - 3;
+    rl_handle.draw_text(&last_label, last_x, bounds.y as i32 + 2, AXIS_FONT_SIZE, COLOR_BLUE);
+}
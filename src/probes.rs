@@ -0,0 +1,294 @@
+use crate::colors::*;
+use crate::fluid_engine::*;
+use crate::logging::EXPORT;
+use crate::recording::next_free_path;
+use crate::view_camera::ViewCamera;
+use raylib::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Mouse travel (pixels) under which a Control + drag places a point probe rather than a line
+const CLICK_DISTANCE: f32 = 4.0;
+const POINT_RADIUS: f32 = 4.0;
+const LABEL_FONT_SIZE: i32 = 14;
+
+/// Where a probe samples the fluid, positions in metres
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Probe {
+    Point((f64, f64)),
+    /// `samples` points evenly spaced from `start` to `end`, both included
+    Line {
+        start: (f64, f64),
+        end: (f64, f64),
+        samples: usize,
+    },
+}
+impl Probe {
+    /// Line probe with about one sample per cell
+    pub fn line(start: (f64, f64), end: (f64, f64), grid_spacing: f64) -> Self {
+        let length = (end.0 - start.0).hypot(end.1 - start.1);
+        Probe::Line {
+            start,
+            end,
+            samples: (length / grid_spacing).ceil() as usize + 1,
+        }
+    }
+
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        match *self {
+            Probe::Point(position) => vec![position],
+            Probe::Line { start, end, samples } => (0..samples)
+                .map(|sample_id| {
+                    let t = sample_id as f64 / (samples - 1).max(1) as f64;
+                    (start.0 + t * (end.0 - start.0), start.1 + t * (end.1 - start.1))
+                })
+                .collect(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Probe::Point(_) => "point",
+            Probe::Line { .. } => "line",
+        }
+    }
+
+    /// Distance from `position` to the probe (metres)
+    fn distance(&self, position: (f64, f64)) -> f64 {
+        let (start, end) = match *self {
+            Probe::Point(point) => (point, point),
+            Probe::Line { start, end, .. } => (start, end),
+        };
+        let (segment_x, segment_y) = (end.0 - start.0, end.1 - start.1);
+        let length_squared = segment_x * segment_x + segment_y * segment_y;
+        let t = if length_squared > 0.0 {
+            (((position.0 - start.0) * segment_x + (position.1 - start.1) * segment_y) / length_squared).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (start.0 + t * segment_x - position.0).hypot(start.1 + t * segment_y - position.1)
+    }
+}
+
+/// Fluid quantities at one position, from the bilinear samplers of `FluidDomain`
+#[derive(Clone, Copy, Debug)]
+pub struct ProbeSample {
+    pub u: f64,
+    pub v: f64,
+    pub pressure: f64,
+    pub dye: f64,
+}
+impl ProbeSample {
+    pub fn at(fluid_domain: &FluidDomain, (x, y): (f64, f64)) -> Self {
+        ProbeSample {
+            u: fluid_domain.sample_grid_velocity_u(x, y),
+            v: fluid_domain.sample_grid_velocity_v(x, y),
+            pressure: fluid_domain.sample_grid_pressure(x, y),
+            dye: fluid_domain.sample_grid_dye(x, y),
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.u.hypot(self.v)
+    }
+}
+
+/// CSV log of probes, one line per probe point and step.
+/// Columns: step, time, probe, kind (point or line), point, x, y, u, v, pressure, dye
+pub struct ProbeLog {
+    writer: BufWriter<File>,
+}
+impl ProbeLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+
+    /// Log continuing `path`, for runs resumed from a snapshot
+    pub fn append(path: &Path) -> io::Result<Self> {
+        Self::new(OpenOptions::new().create(true).append(true).open(path)?)
+    }
+
+    fn new(file: File) -> io::Result<Self> {
+        let is_empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_empty {
+            writeln!(writer, "step,time,probe,kind,point,x,y,u,v,pressure,dye")?;
+        }
+        Ok(ProbeLog { writer })
+    }
+
+    /// Sample every probe, given with its id, after `step` steps of `fluid_domain`
+    pub fn write_step<'a>(
+        &mut self,
+        step: usize,
+        fluid_domain: &FluidDomain,
+        probes: impl Iterator<Item = (usize, &'a Probe)>,
+    ) -> io::Result<()> {
+        let time = step as f64 * fluid_domain.timestep;
+        for (probe_id, probe) in probes {
+            for (point_id, position) in probe.points().into_iter().enumerate() {
+                let sample = ProbeSample::at(fluid_domain, position);
+                writeln!(
+                    self.writer,
+                    "{},{},{},{},{},{},{},{},{},{},{}",
+                    step,
+                    time,
+                    probe_id,
+                    probe.kind(),
+                    point_id,
+                    position.0,
+                    position.1,
+                    sample.u,
+                    sample.v,
+                    sample.pressure,
+                    sample.dye
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Probes placed with the mouse, logged every step while there are any.
+///
+/// `Control + left click` places a point probe, `Control + left drag` a line probe and
+/// `Control + right click` removes the closest probe. The log goes to
+/// `<export_prefix>_probes_<index>.csv`, a new file being started once all probes were removed.
+pub struct ProbeSet {
+    /// Probes with their id, ids are not reused so the log stays readable after removals
+    probes: Vec<(usize, Probe)>,
+    next_id: usize,
+    /// Position (metres) and screen position where the Control + left drag started
+    drag_start: Option<((f64, f64), Vector2)>,
+    log: Option<ProbeLog>,
+    export_prefix: String,
+}
+impl ProbeSet {
+    pub fn new(export_prefix: &str) -> Self {
+        ProbeSet {
+            probes: Vec::new(),
+            next_id: 0,
+            drag_start: None,
+            log: None,
+            export_prefix: export_prefix.to_string(),
+        }
+    }
+
+    pub fn update(&mut self, rl_handle: &RaylibHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        let control = rl_handle.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl_handle.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        let mouse_position = rl_handle.get_mouse_position();
+        let (grid_x, grid_y) = view.screen_to_grid(mouse_position);
+        let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+        let in_grid = grid_x >= 0.0 && grid_y >= 0.0 && grid_x < grid_size_x as f64 && grid_y < grid_size_y as f64;
+        let position = (grid_x * fluid_domain.grid_spacing, grid_y * fluid_domain.grid_spacing);
+
+        if control && in_grid && rl_handle.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            self.drag_start = Some((position, mouse_position));
+        }
+        if rl_handle.is_mouse_button_released(MouseButton::MOUSE_BUTTON_LEFT) {
+            if let Some((start, start_screen)) = self.drag_start.take() {
+                let probe = if (mouse_position - start_screen).length() < CLICK_DISTANCE || !in_grid {
+                    Probe::Point(start)
+                } else {
+                    Probe::line(start, position, fluid_domain.grid_spacing)
+                };
+                self.add(probe);
+            }
+        }
+        if control && rl_handle.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+            let closest = self
+                .probes
+                .iter()
+                .enumerate()
+                .min_by(|(_, (_, a)), (_, (_, b))| a.distance(position).total_cmp(&b.distance(position)));
+            if let Some((index, _)) = closest {
+                self.probes.remove(index);
+            }
+            if self.probes.is_empty() {
+                self.close_log();
+            }
+        }
+    }
+
+    fn add(&mut self, probe: Probe) {
+        if self.log.is_none() {
+            let path = next_free_path(&format!("{}_probes", self.export_prefix), ".csv");
+            match ProbeLog::create(&path) {
+                Ok(log) => {
+                    log::info!(target: EXPORT, "Probes logged to {}", path.display());
+                    self.log = Some(log);
+                }
+                Err(error) => log::error!(target: EXPORT, "Unable to write {}: {}", path.display(), error),
+            }
+        }
+        self.probes.push((self.next_id, probe));
+        self.next_id += 1;
+    }
+
+    fn close_log(&mut self) {
+        if let Some(mut log) = self.log.take() {
+            if let Err(error) = log.flush() {
+                log::error!(target: EXPORT, "Unable to write the probe log: {}", error);
+            }
+        }
+    }
+
+    /// Log the probes after `step` steps of `fluid_domain`, flushed so the file can be followed
+    /// while the scene runs
+    pub fn record(&mut self, fluid_domain: &FluidDomain, step: usize) {
+        let Some(log) = self.log.as_mut() else {
+            return;
+        };
+        let probes = self.probes.iter().map(|(probe_id, probe)| (*probe_id, probe));
+        if let Err(error) = log.write_step(step, fluid_domain, probes).and_then(|()| log.flush()) {
+            log::error!(target: EXPORT, "Unable to write the probe log, logging stopped: {}", error);
+            self.log = None;
+        }
+    }
+
+    /// Speed at every point probe, with its id
+    pub fn point_speeds(&self, fluid_domain: &FluidDomain) -> Vec<(usize, f64)> {
+        self.probes
+            .iter()
+            .filter_map(|(probe_id, probe)| match *probe {
+                Probe::Point(position) => Some((*probe_id, ProbeSample::at(fluid_domain, position).speed())),
+                Probe::Line { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Probes with their id, and the line being dragged
+    pub fn draw(&self, rl_handle: &mut RaylibDrawHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        let to_screen = |point: (f64, f64)| {
+            view.grid_to_screen((point.0 / fluid_domain.grid_spacing, point.1 / fluid_domain.grid_spacing))
+        };
+        for (probe_id, probe) in self.probes.iter() {
+            let label_position = match *probe {
+                Probe::Point(position) => to_screen(position),
+                Probe::Line { start, end, .. } => {
+                    rl_handle.draw_line_ex(to_screen(start), to_screen(end), 2.0, COLOR_YELLOW);
+                    rl_handle.draw_circle_v(to_screen(end), POINT_RADIUS - 1.0, COLOR_YELLOW);
+                    to_screen(start)
+                }
+            };
+            rl_handle.draw_circle_v(label_position, POINT_RADIUS, COLOR_YELLOW);
+            rl_handle.draw_circle_lines(label_position.x as i32, label_position.y as i32, POINT_RADIUS, COLOR_BLACK);
+            rl_handle.draw_text(
+                &probe_id.to_string(),
+                (label_position.x + POINT_RADIUS + 2.0) as i32,
+                (label_position.y - POINT_RADIUS) as i32 - LABEL_FONT_SIZE,
+                LABEL_FONT_SIZE,
+                COLOR_YELLOW,
+            );
+        }
+        if let Some((_, start_screen)) = self.drag_start {
+            rl_handle.draw_line_ex(start_screen, rl_handle.get_mouse_position(), 2.0, COLOR_YELLOW);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::colors::*;
+use crate::fluid_engine::*;
+use crate::view_camera::ViewCamera;
+use raylib::prelude::*;
+
+const MIN_STRIDE: usize = 2;
+const MAX_STRIDE: usize = 64;
+/// Arrow head length relative to the arrow length
+const HEAD_RATIO: f32 = 0.3;
+/// Angle between the arrow shaft and each side of its head (radians)
+const HEAD_ANGLE: f32 = 0.45;
+
+/// Velocity arrows drawn over the scalar view, one every `stride` cells.
+pub struct QuiverOverlay {
+    pub enabled: bool,
+    /// Distance between two arrows, in cells
+    pub stride: usize,
+    /// Every arrow has the same length instead of being scaled by the local speed
+    pub normalised: bool,
+}
+impl QuiverOverlay {
+    pub fn new() -> Self {
+        QuiverOverlay {
+            enabled: false,
+            stride: 8,
+            normalised: false,
+        }
+    }
+
+    pub fn help_text() -> Vec<&'static str> {
+        vec!["Q: toggle velocity arrows, M: scaled / normalised, - / =: arrow spacing"]
+    }
+
+    /// `Q` toggles the arrows, `M` switches between scaled and normalised arrows,
+    /// `-` / `=` change the stride.
+    pub fn update(&mut self, rl_handle: &RaylibHandle) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_Q) {
+            self.enabled = !self.enabled;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_M) {
+            self.normalised = !self.normalised;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_MINUS) {
+            self.stride = (self.stride / 2).max(MIN_STRIDE);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_EQUAL) {
+            self.stride = (self.stride * 2).min(MAX_STRIDE);
+        }
+    }
+
+    /// Arrows at the centre of the fluid cells, sampled from the staggered velocities.
+    /// Scaled arrows are as long as the stride at the highest sampled speed.
+    pub fn draw(&self, rl_handle: &mut RaylibDrawHandle, fluid_domain: &FluidDomain, view: &ViewCamera) {
+        if !self.enabled {
+            return;
+        }
+        let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+        let spacing = fluid_domain.grid_spacing;
+        let first_cell = self.stride / 2;
+
+        let mut samples = Vec::new();
+        for x_id in (first_cell..grid_size_x).step_by(self.stride) {
+            for y_id in (first_cell..grid_size_y).step_by(self.stride) {
+                if fluid_domain.fluid_grid[x_id][y_id].state != CellState::Fluid {
+                    continue;
+                }
+                let (x, y) = ((x_id as f64 + 0.5) * spacing, (y_id as f64 + 0.5) * spacing);
+                let velocity = (
+                    fluid_domain.sample_grid_velocity_u(x, y),
+                    fluid_domain.sample_grid_velocity_v(x, y),
+                );
+                samples.push(((x_id as f64 + 0.5, y_id as f64 + 0.5), velocity));
+            }
+        }
+        let max_speed = samples
+            .iter()
+            .map(|(_, (u, v))| u.hypot(*v))
+            .fold(0.0, f64::max);
+        if max_speed <= 0.0 {
+            return;
+        }
+
+        for ((x, y), (u, v)) in samples {
+            let speed = u.hypot(v);
+            if speed <= 0.0 {
+                continue;
+            }
+            // Arrow length in cells
+            let length = if self.normalised {
+                0.8 * self.stride as f64
+            } else {
+                self.stride as f64 * speed / max_speed
+            };
+            let start = view.grid_to_screen((x, y));
+            let end = view.grid_to_screen((x + u / speed * length, y + v / speed * length));
+            draw_arrow(rl_handle, start, end);
+        }
+    }
+}
+
+fn draw_arrow(rl_handle: &mut RaylibDrawHandle, start: Vector2, end: Vector2) {
+    let shaft = end - start;
+    let length = shaft.length();
+    if length < 1.0 {
+        return;
+    }
+    rl_handle.draw_line_v(start, end, COLOR_BLACK);
+    let back = shaft / length * -(length * HEAD_RATIO);
+    for angle in [HEAD_ANGLE, -HEAD_ANGLE] {
+        rl_handle.draw_line_v(end, end + back.rotated(angle), COLOR_BLACK);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::field_image::PixelBuffer;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+mod gif;
+use gif::GifEncoder;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// `frame_<index>.png` files in a directory
+    Png,
+    /// Single looping animated GIF
+    Gif,
+}
+impl RecordingFormat {
+    /// GIF for paths ending in `.gif`, numbered PNG files otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension == "gif" => RecordingFormat::Gif,
+            _ => RecordingFormat::Png,
+        }
+    }
+}
+
+/// Writes rendered frames to disk at a fixed frame rate.
+///
+/// The caller gives a time with every frame (wall clock in a window, simulated time when
+/// headless) and frames closer than `1 / fps` to the previous one are dropped.
+pub struct Recorder {
+    path: PathBuf,
+    format: RecordingFormat,
+    fps: u32,
+    next_frame_time: Option<f64>,
+    frame_count: usize,
+    gif_encoder: Option<GifEncoder<BufWriter<File>>>,
+}
+impl Recorder {
+    pub fn new(path: &Path, fps: u32) -> io::Result<Self> {
+        let format = RecordingFormat::from_path(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if format == RecordingFormat::Png {
+            fs::create_dir_all(path)?;
+        }
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            format,
+            fps: fps.max(1),
+            next_frame_time: None,
+            frame_count: 0,
+            gif_encoder: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Record `frame` if a frame is due at `time` (in seconds).
+    pub fn capture(&mut self, frame: &PixelBuffer, time: f64) -> io::Result<()> {
+        if self.next_frame_time.is_some_and(|next_frame_time| time < next_frame_time) {
+            return Ok(());
+        }
+        let frame_interval = 1.0 / self.fps as f64;
+        let mut next_frame_time = self.next_frame_time.unwrap_or(time) + frame_interval;
+        // Skip frames instead of catching up when the simulation is slower than the recording
+        if next_frame_time <= time {
+            next_frame_time = time + frame_interval;
+        }
+        self.next_frame_time = Some(next_frame_time);
+
+        match self.format {
+            RecordingFormat::Png => {
+                let frame_path = self.path.join(format!("frame_{:05}.png", self.frame_count));
+                frame.to_image().export_image(&frame_path.to_string_lossy());
+            }
+            RecordingFormat::Gif => {
+                if self.gif_encoder.is_none() {
+                    self.gif_encoder = Some(GifEncoder::new(
+                        BufWriter::new(File::create(&self.path)?),
+                        frame.width() as u16,
+                        frame.height() as u16,
+                        self.fps,
+                    )?);
+                }
+                if let Some(gif_encoder) = self.gif_encoder.as_mut() {
+                    gif_encoder.write_frame(&frame.colors())?;
+                }
+            }
+        }
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    /// Close the recording, returning the number of frames written.
+    pub fn finish(self) -> io::Result<usize> {
+        if let Some(gif_encoder) = self.gif_encoder {
+            gif_encoder.finish()?;
+        }
+        Ok(self.frame_count)
+    }
+}
+
+/// First `<prefix>_<index><extension>` path that does not exist yet
+pub fn next_free_path(prefix: &str, extension: &str) -> PathBuf {
+    (0..)
+        .map(|index| PathBuf::from(format!("{}_{:03}{}", prefix, index, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
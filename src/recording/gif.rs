@@ -0,0 +1,143 @@
+use raylib::prelude::Color;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+const MIN_CODE_SIZE: u8 = 8;
+const MAX_CODE: u16 = 4096;
+
+/// Minimal animated GIF89a writer using a fixed 6x7x6 colour cube palette.
+pub struct GifEncoder<W: Write> {
+    writer: W,
+    width: u16,
+    height: u16,
+    /// Frame delay in hundredths of a second
+    delay: u16,
+}
+impl<W: Write> GifEncoder<W> {
+    pub fn new(mut writer: W, width: u16, height: u16, fps: u32) -> io::Result<Self> {
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        // Global colour table of 256 entries, 8 bits per primary colour
+        writer.write_all(&[0xF7, 0, 0])?;
+        for index in 0..=255u8 {
+            let color = palette_color(index);
+            writer.write_all(&[color.r, color.g, color.b])?;
+        }
+        // Loop forever
+        writer.write_all(&[0x21, 0xFF, 0x0B])?;
+        writer.write_all(b"NETSCAPE2.0")?;
+        writer.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+
+        Ok(GifEncoder {
+            writer,
+            width,
+            height,
+            delay: (100 / fps.max(1)).max(1) as u16,
+        })
+    }
+
+    /// `pixels` are row by row, `width * height` of them.
+    pub fn write_frame(&mut self, pixels: &[Color]) -> io::Result<()> {
+        let indices: Vec<u8> = pixels.iter().map(|color| palette_index(*color)).collect();
+
+        // Graphic control extension holding the frame delay
+        self.writer.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        self.writer.write_all(&self.delay.to_le_bytes())?;
+        self.writer.write_all(&[0x00, 0x00])?;
+        // Image descriptor covering the whole screen
+        self.writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.writer.write_all(&self.width.to_le_bytes())?;
+        self.writer.write_all(&self.height.to_le_bytes())?;
+        self.writer.write_all(&[0x00, MIN_CODE_SIZE])?;
+
+        for block in lzw_encode(&indices).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0x00])
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()
+    }
+}
+
+fn palette_index(color: Color) -> u8 {
+    let r = (color.r as u32 * 5 + 127) / 255;
+    let g = (color.g as u32 * 6 + 127) / 255;
+    let b = (color.b as u32 * 5 + 127) / 255;
+    (r * 42 + g * 6 + b) as u8
+}
+
+fn palette_color(index: u8) -> Color {
+    let index = (index as u32).min(251);
+    let (r, g, b) = (index / 42, (index % 42) / 6, index % 6);
+    Color::new((r * 255 / 5) as u8, (g * 255 / 6) as u8, (b * 255 / 5) as u8, 255)
+}
+
+/// Variable length LZW codes as used by GIF, packed least significant bit first.
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    let clear_code = 1u16 << MIN_CODE_SIZE;
+    let end_code = clear_code + 1;
+    let mut bits = BitWriter::default();
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = MIN_CODE_SIZE as u32 + 1;
+    let mut next_code = end_code + 1;
+
+    bits.write(clear_code, code_size);
+    let Some((&first, rest)) = indices.split_first() else {
+        bits.write(end_code, code_size);
+        return bits.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = dictionary.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        bits.write(prefix, code_size);
+        if next_code < MAX_CODE {
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            dictionary.insert((prefix, index), next_code);
+            next_code += 1;
+        } else {
+            bits.write(clear_code, code_size);
+            dictionary.clear();
+            code_size = MIN_CODE_SIZE as u32 + 1;
+            next_code = end_code + 1;
+        }
+        prefix = index as u16;
+    }
+    bits.write(prefix, code_size);
+    bits.write(end_code, code_size);
+    bits.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    length: u32,
+}
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.length;
+        self.length += size;
+        while self.length >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.length -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.length > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
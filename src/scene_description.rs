@@ -0,0 +1,366 @@
+use crate::colors::Colormap;
+use crate::domain_mask::DomainMask;
+use crate::field_image::{ColorScale, RangeMode};
+use crate::fluid_engine::*;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Data-driven scene, read from a TOML file.
+///
+/// Positions and sizes are in metres, the grid y axis points down (row 0 is the top of the view).
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub title: String,
+    pub grid: GridDescription,
+    #[serde(default)]
+    pub boundaries: BoundariesDescription,
+    /// Image painting walls, inflow, outflow and dye sources over the grid
+    #[serde(default)]
+    pub mask: Option<MaskDescription>,
+    /// Mask image read by `load`
+    #[serde(skip)]
+    pub domain_mask: Option<DomainMask>,
+    #[serde(default)]
+    pub obstacles: Vec<ShapeDescription>,
+    #[serde(default)]
+    pub sources: Vec<SourceDescription>,
+    /// Regions filled with liquid, a free surface simulation is used if any is given
+    #[serde(default)]
+    pub liquid: Vec<ShapeDescription>,
+    #[serde(default)]
+    pub forces: ForcesDescription,
+    #[serde(default)]
+    pub display: DisplayDescription,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GridDescription {
+    pub size: [usize; 2],
+    #[serde(default = "default_grid_spacing")]
+    pub spacing: f64,
+    pub timestep: f64,
+}
+
+fn default_grid_spacing() -> f64 {
+    DEFAULT_GRID_SPACING
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoundaryKind {
+    #[default]
+    Wall,
+    Inflow,
+    Outflow,
+    /// Border cells are left as fluid
+    Open,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoundariesDescription {
+    #[serde(default)]
+    pub left: BoundaryKind,
+    #[serde(default)]
+    pub right: BoundaryKind,
+    #[serde(default)]
+    pub top: BoundaryKind,
+    #[serde(default)]
+    pub bottom: BoundaryKind,
+    #[serde(default)]
+    pub inflow_velocity: [f64; 2],
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaskDescription {
+    /// Relative to the scene file
+    pub path: PathBuf,
+    /// Resample the image to the grid size, otherwise both sizes must match
+    #[serde(default)]
+    pub resample: bool,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "shape", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ShapeDescription {
+    Rectangle { min: [f64; 2], max: [f64; 2] },
+    Circle { center: [f64; 2], radius: f64 },
+}
+impl ShapeDescription {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            ShapeDescription::Rectangle { min, max } => {
+                x >= min[0] && x <= max[0] && y >= min[1] && y <= max[1]
+            }
+            ShapeDescription::Circle { center, radius } => {
+                (x - center[0]).powi(2) + (y - center[1]).powi(2) <= radius * radius
+            }
+        }
+    }
+}
+
+/// Region where dye and/or velocity are imposed at every step.
+///
+/// `deny_unknown_fields` does not combine with `flatten`, but the fields this struct does not
+/// know go to `ShapeDescription`, which rejects them. `SceneDescription::parse` checks that
+/// every source imposes something.
+#[derive(Clone, Deserialize)]
+pub struct SourceDescription {
+    #[serde(flatten)]
+    pub region: ShapeDescription,
+    #[serde(default)]
+    pub dye: Option<f64>,
+    #[serde(default)]
+    pub velocity: Option<[f64; 2]>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForcesDescription {
+    #[serde(default)]
+    pub gravity: [f64; 2],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisplayField {
+    VelocityX,
+    VelocityY,
+    #[default]
+    Pressure,
+    Dye,
+    Speed,
+    Vorticity,
+    /// Divergence left by the last pressure projection, `FluidCell::divergence`
+    Divergence,
+    QCriterion,
+    StreamFunction,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisplayDescription {
+    #[serde(default)]
+    pub field: DisplayField,
+    /// Defaults to coolwarm for signed fields and viridis otherwise
+    #[serde(default)]
+    pub colormap: Option<Colormap>,
+    /// Locked range `[min, max]`, fitted to every frame when missing
+    #[serde(default)]
+    pub range: Option<[f64; 2]>,
+    /// Range centred on zero, the default for signed fields
+    #[serde(default)]
+    pub symmetric: Option<bool>,
+    #[serde(default)]
+    pub log_scale: bool,
+    /// Labelled contours shown from the start at these levels
+    #[serde(default)]
+    pub contour_levels: Vec<f64>,
+}
+impl DisplayDescription {
+    pub fn color_scale(&self) -> ColorScale {
+        let mut color_scale = ColorScale::for_field(self.field);
+        if let Some(colormap) = self.colormap {
+            color_scale.colormap = colormap;
+        }
+        match self.symmetric {
+            Some(true) => color_scale.range_mode = RangeMode::Symmetric,
+            Some(false) => color_scale.range_mode = RangeMode::Auto,
+            None => {}
+        }
+        if let Some([min, max]) = self.range {
+            color_scale.range_mode = RangeMode::Locked;
+            color_scale.range = (min, max);
+        }
+        color_scale.log_scale = self.log_scale;
+        color_scale
+    }
+}
+
+impl SceneDescription {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let mut description = Self::parse(&text, path)?;
+
+        if let Some(mask) = &description.mask {
+            let grid_size = (description.grid.size[0], description.grid.size[1]);
+            let mask_path = path.parent().unwrap_or(Path::new(".")).join(&mask.path);
+            let domain_mask = DomainMask::load(&mask_path, mask.resample.then_some(grid_size))?;
+            if domain_mask.grid_size() != grid_size {
+                return Err(format!(
+                    "Invalid scene {}: mask is {}x{} but the grid is {}x{}, set resample = true",
+                    path.display(),
+                    domain_mask.grid_size().0,
+                    domain_mask.grid_size().1,
+                    grid_size.0,
+                    grid_size.1
+                ));
+            }
+            description.domain_mask = Some(domain_mask);
+        }
+        Ok(description)
+    }
+
+    /// Scene described by `text`, checked but without its mask image, `path` naming it in errors
+    pub fn parse(text: &str, path: &Path) -> Result<Self, String> {
+        let description: SceneDescription =
+            toml::from_str(text).map_err(|e| format!("Invalid scene {}: {}", path.display(), e))?;
+
+        let grid_sizes = MIN_GRID_SIZE..=MAX_GRID_SIZE;
+        if !grid_sizes.contains(&description.grid.size[0]) || !grid_sizes.contains(&description.grid.size[1]) {
+            return Err(format!(
+                "Invalid scene {}: grid must be between {}x{} and {}x{}",
+                path.display(),
+                MIN_GRID_SIZE,
+                MIN_GRID_SIZE,
+                MAX_GRID_SIZE,
+                MAX_GRID_SIZE
+            ));
+        }
+        if description.grid.spacing <= 0.0 || description.grid.timestep <= 0.0 {
+            return Err(format!(
+                "Invalid scene {}: spacing and timestep must be positive",
+                path.display()
+            ));
+        }
+        if let Some([min, max]) = description.display.range {
+            if min >= max {
+                return Err(format!("Invalid scene {}: display range must be increasing", path.display()));
+            }
+        }
+        // The particle to grid transfer of liquid scenes would overwrite the imposed velocities
+        if !description.liquid.is_empty() && description.sources.iter().any(|source| source.velocity.is_some()) {
+            return Err(format!(
+                "Invalid scene {}: velocity sources are not supported with liquid",
+                path.display()
+            ));
+        }
+        if let Some(source_id) = description
+            .sources
+            .iter()
+            .position(|source| source.dye.is_none() && source.velocity.is_none())
+        {
+            return Err(format!(
+                "Invalid scene {}: source {} imposes neither dye nor velocity",
+                path.display(),
+                source_id + 1
+            ));
+        }
+        Ok(description)
+    }
+
+    /// Centre of a cell in metres
+    pub fn cell_center(&self, x_id: usize, y_id: usize) -> (f64, f64) {
+        (
+            (x_id as f64 + 0.5) * self.grid.spacing,
+            (y_id as f64 + 0.5) * self.grid.spacing,
+        )
+    }
+
+    /// Domain with its mask, boundaries and obstacles, without any source applied
+    pub fn build_domain(&self) -> FluidDomain {
+        let (grid_size_x, grid_size_y) = (self.grid.size[0], self.grid.size[1]);
+        let mut fluid_domain = match &self.domain_mask {
+            Some(domain_mask) => domain_mask.build_domain(self.grid.timestep),
+            None => FluidDomain::new(grid_size_x, grid_size_y, self.grid.timestep),
+        };
+        fluid_domain.grid_spacing = self.grid.spacing;
+        fluid_domain.inflow_velocity = (
+            self.boundaries.inflow_velocity[0],
+            self.boundaries.inflow_velocity[1],
+        );
+
+        for x_id in 0..grid_size_x {
+            set_boundary(&mut fluid_domain, x_id, 0, self.boundaries.top);
+            set_boundary(&mut fluid_domain, x_id, grid_size_y - 1, self.boundaries.bottom);
+        }
+        for y_id in 0..grid_size_y {
+            set_boundary(&mut fluid_domain, 0, y_id, self.boundaries.left);
+            set_boundary(&mut fluid_domain, grid_size_x - 1, y_id, self.boundaries.right);
+        }
+
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                let (x, y) = self.cell_center(x_id, y_id);
+                if self.obstacles.iter().any(|obstacle| obstacle.contains(x, y)) {
+                    fluid_domain.set_cell_state(x_id, y_id, CellState::Wall);
+                }
+            }
+        }
+
+        fluid_domain
+    }
+}
+
+fn set_boundary(fluid_domain: &mut FluidDomain, x_id: usize, y_id: usize, kind: BoundaryKind) {
+    let state = match kind {
+        BoundaryKind::Wall => CellState::Wall,
+        BoundaryKind::Inflow => CellState::Inflow,
+        BoundaryKind::Outflow => CellState::Outflow,
+        BoundaryKind::Open => return,
+    };
+    fluid_domain.set_cell_state(x_id, y_id, state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: &str = "title = \"Test\"\n[grid]\nsize = [32, 16]\ntimestep = 0.01\n";
+
+    fn parse(text: &str) -> Result<SceneDescription, String> {
+        SceneDescription::parse(text, Path::new("test.toml"))
+    }
+
+    #[test]
+    fn scene_files_are_parsed() {
+        for path in ["scenes/channel_cylinder.toml", "scenes/liquid_tank.toml", "scenes/backward_step.toml"] {
+            let text = std::fs::read_to_string(path).unwrap();
+            assert!(parse(&text).is_ok(), "{}", path);
+        }
+        let description = parse(&format!(
+            "{}[[obstacles]]\nshape = \"circle\"\ncenter = [8.0, 8.0]\nradius = 2.0\n\
+             [[sources]]\nshape = \"rectangle\"\nmin = [1.0, 1.0]\nmax = [3.0, 3.0]\nvelocity = [1.0, 0.0]\n",
+            GRID
+        ))
+        .unwrap();
+        assert_eq!(description.grid.size, [32, 16]);
+        assert_eq!(description.grid.spacing, DEFAULT_GRID_SPACING);
+        assert!(description.obstacles[0].contains(8.0, 9.5));
+        assert_eq!(description.sources[0].velocity, Some([1.0, 0.0]));
+        assert_eq!(description.sources[0].dye, None);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for shape in [
+            "[[obstacles]]\nshape = \"circle\"\ncenter = [8.0, 8.0]\nradus = 2.0\n",
+            "[[obstacles]]\nshape = \"circle\"\ncenter = [8.0, 8.0]\nradius = 2.0\ncolor = 1\n",
+            "[[sources]]\nshape = \"circle\"\ncenter = [8.0, 8.0]\nradius = 2.0\ndye = 1.0\nveloctiy = [1.0, 0.0]\n",
+            "[[sources]]\nshape = \"circle\"\ncenter = [8.0, 8.0]\nradius = 2.0\nveloctiy = [1.0, 0.0]\n",
+            "[grid2]\n",
+        ] {
+            assert!(parse(&format!("{}{}", GRID, shape)).is_err(), "{}", shape);
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let grid = |size: &str| format!("title = \"Test\"\n[grid]\nsize = {}\ntimestep = 0.01\n", size);
+        assert!(parse(&grid("[2, 16]")).is_err());
+        assert!(parse(&grid("[100000, 100000]")).is_err());
+        assert!(parse(&grid(&format!("[{}, 3]", MAX_GRID_SIZE))).is_ok());
+        assert!(parse(&grid("[32, 16]").replace("0.01", "0.0")).is_err());
+        assert!(parse(&format!("{}[display]\nrange = [1.0, -1.0]\n", GRID)).is_err());
+        assert!(parse(&format!(
+            "{}[[liquid]]\nshape = \"rectangle\"\nmin = [1.0, 1.0]\nmax = [9.0, 9.0]\n\
+             [[sources]]\nshape = \"rectangle\"\nmin = [1.0, 1.0]\nmax = [3.0, 3.0]\nvelocity = [1.0, 0.0]\n",
+            GRID
+        ))
+        .is_err());
+    }
+}
@@ -1,17 +1,230 @@
-use raylib::RaylibHandle;
+use crate::export::write_npy_fields;
+use crate::field_image::PixelBuffer;
+use crate::fluid_engine::{Diagnostics, FluidDomain, SnapshotHeader};
+use crate::input_journal::{snapshot_hash, InputJournal, SceneInput};
+use crate::logging::{EXPORT, SCENE};
+use crate::recording::next_free_path;
+use crate::simulation::Simulation;
+use crate::view_camera::ViewCamera;
+use crate::wall_editor::WallEditor;
+use raylib::consts::{KeyboardKey, MouseButton};
+use raylib::{RaylibHandle, RaylibThread};
 use raylib::drawing::RaylibDrawHandle;
+use std::path::Path;
 
 mod basic_fluid;
-pub use basic_fluid::BasicFuildScene;
+pub use basic_fluid::{BasicFluidSimulation, BasicFuildScene};
 mod advection;
-pub use advection::AdvectionFuildScene;
+pub use advection::{AdvectionFuildScene, AdvectionSimulation};
+mod dam_break;
+pub use dam_break::{DamBreakScene, DamBreakSimulation};
+mod described;
+pub use described::{DescribedScene, DescribedSimulation};
+
+/// Directory searched for scene description files (`*.toml`)
+pub const SCENES_DIRECTORY: &str = "scenes";
+
+/// Command line names of the built-in scenes that can run without a window
+pub const SIMULATION_NAMES: [&str; 3] = ["basic", "advection", "dam-break"];
+
+/// Built-in scene by command line name, or scene description file path
+pub fn simulation_by_name(name: &str) -> Result<Box<dyn Simulation>, String> {
+    match name {
+        "basic" => Ok(Box::new(BasicFluidSimulation::new())),
+        "advection" => Ok(Box::new(AdvectionSimulation::new())),
+        "dam-break" => Ok(Box::new(DamBreakSimulation::new())),
+        _ if name.ends_with(".toml") => Ok(Box::new(DescribedSimulation::load(Path::new(name))?)),
+        _ => Err(format!(
+            "Unknown scene: {}, available scenes: {} or a scene file",
+            name,
+            SIMULATION_NAMES.join(", ")
+        )),
+    }
+}
+
+/// Write the domain of `simulation` after `step` steps to `path`, reporting the result on the console
+pub fn save_snapshot(simulation: &dyn Simulation, step: usize, path: &Path) {
+    let header = SnapshotHeader {
+        step,
+        scene_state: simulation.scene_state(),
+    };
+    match simulation.fluid_domain().save_snapshot(&header, path) {
+        Ok(()) => log::info!(target: SCENE, "Snapshot written to {}", path.display()),
+        Err(error) => log::error!(target: SCENE, "Unable to write {}: {}", path.display(), error),
+    }
+}
+
+/// Restore the domain of `simulation` from `path`, reporting the result on the console.
+/// Refused when `expected_hash` is given and the file does not match it. Returns the
+/// `snapshot_hash` of the restored file.
+pub fn restore_snapshot(simulation: &mut dyn Simulation, path: &Path, expected_hash: Option<u64>) -> Option<u64> {
+    let restored = std::fs::read(path).map_err(|e| e.to_string()).and_then(|bytes| {
+        let hash = snapshot_hash(&bytes);
+        if expected_hash.is_some_and(|expected_hash| expected_hash != hash) {
+            return Err("the file changed since the journal was recorded".to_string());
+        }
+        let (fluid_domain, header) = FluidDomain::read_snapshot(&bytes).map_err(|e| e.to_string())?;
+        simulation.restore(fluid_domain, &header.scene_state)?;
+        Ok(hash)
+    });
+    match restored {
+        Ok(hash) => {
+            log::info!(target: SCENE, "Snapshot restored from {}", path.display());
+            Some(hash)
+        }
+        Err(error) => {
+            log::error!(target: SCENE, "Unable to restore {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/// Standard deviation of the impulse given by a mouse drag, in cells
+const DRAG_IMPULSE_RADIUS: f64 = 4.0;
+
+/// Impulse for a left mouse drag over the grid shown by `view`.
+/// The fluid under the mouse is pushed as far as the mouse moved during the frame.
+fn drag_impulse(
+    rl_handle: &RaylibHandle,
+    fluid_domain: &FluidDomain,
+    view: &ViewCamera,
+) -> Option<SceneInput> {
+    if !rl_handle.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
+        return None;
+    }
+    let mouse_delta = rl_handle.get_mouse_delta();
+    if mouse_delta.x == 0.0 && mouse_delta.y == 0.0 {
+        return None;
+    }
+
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    let (grid_x, grid_y) = view.screen_to_grid(rl_handle.get_mouse_position());
+    if grid_x < 0.0 || grid_y < 0.0 || grid_x >= grid_size_x as f64 || grid_y >= grid_size_y as f64 {
+        return None;
+    }
+
+    let spacing = fluid_domain.grid_spacing;
+    // Mouse delta in cells
+    let delta_x = (mouse_delta.x / view.zoom()) as f64;
+    let delta_y = (mouse_delta.y / view.zoom()) as f64;
+    Some(SceneInput::Impulse {
+        position: (grid_x * spacing, grid_y * spacing),
+        velocity: (
+            delta_x * spacing / fluid_domain.timestep,
+            delta_y * spacing / fluid_domain.timestep,
+        ),
+        radius: DRAG_IMPULSE_RADIUS * spacing,
+    })
+}
+
+/// Mouse interactions with the grid shown by `view`: wall edits while the wall editor is
+/// enabled, velocity impulses otherwise. Both go through the input journal. The mouse is left
+/// to the probes (see `ProbeSet`) while Control is held.
+pub fn handle_mouse_inputs(
+    rl_handle: &RaylibHandle,
+    wall_editor: &mut WallEditor,
+    journal: &mut InputJournal,
+    simulation: &mut dyn Simulation,
+    view: &ViewCamera,
+) {
+    if rl_handle.is_key_down(KeyboardKey::KEY_LEFT_CONTROL) || rl_handle.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL) {
+        return;
+    }
+    let mouse_cell = view.screen_to_grid(rl_handle.get_mouse_position());
+    wall_editor.track_domain(journal.domain_generation(), false);
+    for input in wall_editor.update(rl_handle, simulation.fluid_domain(), mouse_cell) {
+        journal.apply(simulation, input);
+    }
+    // Live edits are ignored while replaying, there is nothing to undo
+    wall_editor.track_domain(journal.domain_generation(), journal.is_replaying());
+    if wall_editor.enabled {
+        return;
+    }
+    if let Some(input) = drag_impulse(rl_handle, simulation.fluid_domain(), view) {
+        journal.apply(simulation, input);
+    }
+}
+
+/// Brush, shape preview and status of the wall editor over the grid shown by `view`
+pub fn draw_wall_editor(rl_handle: &mut RaylibDrawHandle, wall_editor: &WallEditor, view: &ViewCamera) {
+    wall_editor.draw(rl_handle, |position| view.grid_to_screen(position), view.zoom());
+}
+
+/// F5 saves the input journal to `path`, F6 restarts the scene and replays the journal saved there
+pub fn handle_journal_keys(
+    rl_handle: &RaylibHandle,
+    journal: &mut InputJournal,
+    simulation: &mut dyn Simulation,
+    path: &Path,
+) {
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_F5) {
+        match journal.save(path) {
+            Ok(()) => log::info!(target: SCENE, "Input journal written to {}", path.display()),
+            Err(error) => log::error!(target: SCENE, "Unable to write {}: {}", path.display(), error),
+        }
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_F6) {
+        match InputJournal::load(path) {
+            Ok(replayed_journal) => {
+                match journal.start_replay(simulation, replayed_journal) {
+                    Ok(()) => log::info!(target: SCENE, "Replaying {}", path.display()),
+                    Err(error) => log::error!(target: SCENE, "Unable to replay {}: {}", path.display(), error),
+                }
+            }
+            Err(error) => log::error!(target: SCENE, "{}", error),
+        }
+    }
+}
+
+/// Write the cell quantities of `simulation` as `.npy` arrays to the first free
+/// `<prefix>_npy_<index>` directory, reporting the result on the console
+pub fn export_npy(simulation: &dyn Simulation, prefix: &Path) {
+    let directory = next_free_path(&format!("{}_npy", prefix.display()), "");
+    match write_npy_fields(simulation.fluid_domain(), &directory) {
+        Ok(()) => log::info!(target: EXPORT, "Fields written to {}", directory.display()),
+        Err(error) => log::error!(target: EXPORT, "Unable to write {}: {}", directory.display(), error),
+    }
+}
+
+/// Load every scene description found in `directory`, sorted by file name.
+/// Files that fail to load are reported and skipped.
+pub fn load_scene_files(
+    rl_handle: &mut RaylibHandle,
+    rl_thread: &RaylibThread,
+    directory: &Path,
+) -> Vec<Box<dyn Scene>> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .collect();
+    paths.sort();
+
+    let mut scenes: Vec<Box<dyn Scene>> = Vec::new();
+    for path in paths {
+        match DescribedScene::load(rl_handle, rl_thread, &path) {
+            Ok(scene) => scenes.push(Box::new(scene)),
+            Err(error) => log::error!(target: SCENE, "{}", error),
+        }
+    }
+    scenes
+}
 
 pub trait Scene {
     fn get_title(&self) -> &str;
     fn has_background(&self) -> bool;
     fn help_text(&self) -> Vec<&str>;
     /// Update the scene (only logic)
-    fn update(&mut self, rl_handle: &mut RaylibHandle);
+    fn update(&mut self, rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread);
     /// Draw one frame of the scene
     fn draw(&mut self, rl_handle: &mut RaylibDrawHandle);
+    /// Last frame rendered by `update`, one pixel per cell
+    fn render_pixels(&mut self) -> &PixelBuffer;
+    fn fluid_domain(&self) -> &FluidDomain;
+    /// Steps done since the scene started, or since its input journal replay started
+    fn steps(&self) -> usize;
+    /// Diagnostics of the last step, `None` before the first one
+    fn diagnostics(&self) -> Option<&Diagnostics>;
 }
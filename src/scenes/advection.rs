@@ -1,125 +1,120 @@
-use crate::colors::*;
+use crate::field_image::{DisplayMode, PixelBuffer};
+use crate::field_renderer::{DisplaySettings, FieldRenderer};
 use crate::fluid_engine::*;
-use crate::scenes::Scene;
+use crate::input_journal::{InputJournal, SceneInput};
+use crate::scenes::{
+    draw_wall_editor, export_npy, handle_journal_keys, handle_mouse_inputs, save_snapshot, Scene,
+};
+use crate::scene_description::DisplayField;
+use crate::simulation::Simulation;
+use crate::view_camera::ViewCamera;
+use crate::wall_editor::WallEditor;
 use raylib::prelude::*;
-use std::ffi::CStr;
+use std::path::Path;
 
 const GRID_SIZE: (usize, usize) = (256, 128);
 const TIMESTEP: f64 = 0.01;
+const SNAPSHOT_PATH: &str = "advection.snapshot";
+const JOURNAL_PATH: &str = "advection.journal";
 
-#[derive(Clone, Copy, Debug)]
-enum ValueToDisplay {
-    VelocityX,
-    VelocityY,
-    Pressure,
+pub struct AdvectionSimulation {
+    pub fluid_domain: FluidDomain,
+    pub send_vel: bool,
 }
+impl AdvectionSimulation {
+    pub fn new() -> Self {
+        let mut fluid_domain = FluidDomain::new(GRID_SIZE.0, GRID_SIZE.1, TIMESTEP);
 
-pub struct AdvectionFuildScene {
-    fluid_domain: FluidDomain<{ GRID_SIZE.0 }, { GRID_SIZE.1 }>,
-    render_image: Image,
-    render_texture: Texture2D,
-    dropdown_select: i32,
-    dropdown_edit_mode: bool,
-    value_to_display: ValueToDisplay,
-    send_vel: bool,
-}
-impl AdvectionFuildScene {
-    pub fn new(rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) -> Self {
-        let mut image = Image::gen_image_color(
-            GRID_SIZE.0 as i32,
-            GRID_SIZE.1 as i32,
-            Color::new(0, 0, 0, 255),
-        );
-        let mut fluid_domain = FluidDomain::new(TIMESTEP);
-
-        let wall_color = Color::new(0, 0, 0, 0);
         // Set static wall as boundary
         for x_id in 0..GRID_SIZE.0 {
             fluid_domain.set_cell_state(x_id, GRID_SIZE.1 - 1, CellState::Wall);
             fluid_domain.set_cell_state(x_id, 0, CellState::Wall);
-            image.draw_pixel(x_id as i32, (GRID_SIZE.1 - 1) as i32, wall_color);
-            image.draw_pixel(x_id as i32, 0, wall_color);
         }
 
-        AdvectionFuildScene {
+        AdvectionSimulation {
             fluid_domain,
-            render_texture: rl_handle
-                .load_texture_from_image(rl_thread, &image)
-                .unwrap(),
-            render_image: image,
-            dropdown_select: 0,
-            dropdown_edit_mode: false,
-            value_to_display: ValueToDisplay::VelocityX,
             send_vel: true,
         }
     }
+}
 
-    fn reset_fuild(&mut self) {
-        self.fluid_domain = FluidDomain::new(TIMESTEP);
-        for x_id in 0..GRID_SIZE.0 {
-            self.fluid_domain
-                .set_cell_state(x_id, GRID_SIZE.1 - 1, CellState::Wall);
-            self.fluid_domain.set_cell_state(x_id, 0, CellState::Wall);
+impl Simulation for AdvectionSimulation {
+    fn fluid_domain(&self) -> &FluidDomain {
+        &self.fluid_domain
+    }
+
+    fn fluid_domain_mut(&mut self) -> &mut FluidDomain {
+        &mut self.fluid_domain
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Inflow toggle, 1.0 when the inflow is on
+    fn scene_state(&self) -> Vec<f64> {
+        vec![if self.send_vel { 1.0 } else { 0.0 }]
+    }
+
+    fn restore(&mut self, fluid_domain: FluidDomain, scene_state: &[f64]) -> Result<(), String> {
+        self.check_snapshot_grid(&fluid_domain)?;
+        self.fluid_domain = fluid_domain;
+        // Snapshots from other scenes keep the current toggle
+        if let Some(&send_vel) = scene_state.first() {
+            self.send_vel = send_vel != 0.0;
         }
+        Ok(())
+    }
 
-        for x_id in 1..self.fluid_domain.fluid_grid.len() - 1 {
-            for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                self.render_image
-                    .draw_pixel(x_id as i32, y_id as i32, Color::new(0, 0, 0, 255));
-            }
+    fn apply_input(&mut self, input: &SceneInput) {
+        if *input == SceneInput::ToggleInflow {
+            self.send_vel = !self.send_vel;
         }
     }
 
-    fn update_image_to_draw(&mut self, value_to_display: ValueToDisplay) {
-        let (mut min_display, mut max_display) = (f64::MAX, 0.0);
-        for x_id in 1..self.fluid_domain.fluid_grid.len() - 1 {
+    fn step(&mut self) {
+        let last_x_id = self.fluid_domain.fluid_grid.len() - 1;
+        if self.send_vel {
+            for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
+                self.fluid_domain.fluid_grid[0][y_id].velocity.0 = 10f64;
+                self.fluid_domain.fluid_grid[last_x_id][y_id].velocity.0 = 10f64;
+            }
+        } else {
             for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                let val = match value_to_display {
-                    ValueToDisplay::VelocityX => {
-                        self.fluid_domain.fluid_grid[x_id][y_id].velocity.0
-                    }
-                    ValueToDisplay::VelocityY => {
-                        self.fluid_domain.fluid_grid[x_id][y_id].velocity.1
-                    }
-                    ValueToDisplay::Pressure => self.fluid_domain.fluid_grid[x_id][y_id].pressure,
-                };
-
-                if min_display > val {
-                    min_display = val;
-                }
-                if max_display < val {
-                    max_display = val;
-                }
+                self.fluid_domain.fluid_grid[0][y_id].velocity.0 = 0f64;
+                self.fluid_domain.fluid_grid[last_x_id][y_id].velocity.0 = 0f64;
             }
         }
 
         for x_id in 1..self.fluid_domain.fluid_grid.len() - 1 {
             for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                let val = match value_to_display {
-                    ValueToDisplay::VelocityX => {
-                        self.fluid_domain.fluid_grid[x_id][y_id].velocity.0
-                    }
-                    ValueToDisplay::VelocityY => {
-                        self.fluid_domain.fluid_grid[x_id][y_id].velocity.1
-                    }
-                    ValueToDisplay::Pressure => self.fluid_domain.fluid_grid[x_id][y_id].pressure,
-                };
-                let display_level = (val - min_display) / (max_display - min_display);
-                let color = if self.fluid_domain.fluid_grid[x_id][y_id].state == CellState::Wall {
-                    Color::new(0, 0, 0, 255)
-                } else {
-                    hsl_to_rgb((1.0 - display_level) / 6.0, 1.0, 1.0)
-                };
-
-                self.render_image
-                    .draw_pixel(x_id as i32, y_id as i32, color);
+                self.fluid_domain.fluid_grid[x_id][y_id].pressure = 0.0;
             }
         }
 
-        // Copy data to shader
-        println!("value_to_display: {:?}", value_to_display);
-        println!("min_value: {}", min_display);
-        println!("max_value: {}", max_display);
+        self.fluid_domain.solve_grid_incompressibility();
+        self.fluid_domain.apply_advection();
+    }
+}
+
+pub struct AdvectionFuildScene {
+    simulation: AdvectionSimulation,
+    journal: InputJournal,
+    wall_editor: WallEditor,
+    view: ViewCamera,
+    renderer: FieldRenderer,
+}
+impl AdvectionFuildScene {
+    pub fn new(rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) -> Self {
+        let display = DisplaySettings::new(DisplayMode::Field(DisplayField::VelocityX));
+
+        AdvectionFuildScene {
+            simulation: AdvectionSimulation::new(),
+            journal: InputJournal::new("advection"),
+            wall_editor: WallEditor::new(),
+            view: ViewCamera::new(),
+            renderer: FieldRenderer::new(rl_handle, rl_thread, GRID_SIZE, display, "advection").unwrap(),
+        }
     }
 }
 
@@ -135,74 +130,75 @@ impl Scene for AdvectionFuildScene {
     }
 
     fn help_text(&self) -> Vec<&str> {
-        vec![]
+        let mut help_text = vec!["Left drag: push the fluid along the mouse"];
+        help_text.extend(WallEditor::help_text());
+        help_text.extend(FieldRenderer::help_text());
+        help_text.extend([
+            "R: reset the fluid and the inflow",
+            "V: toggle the inflow velocity",
+            "S: save a snapshot to advection.snapshot",
+            "L: restore the snapshot from advection.snapshot",
+            "N: write the fields as .npy arrays to advection_npy_<index>",
+            "F5: save the input journal to advection.journal",
+            "F6: replay advection.journal from the start",
+        ]);
+        help_text
     }
 
-    fn update(&mut self, rl_handle: &mut RaylibHandle) {
+    fn update(&mut self, rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) {
         if rl_handle.is_key_pressed(KeyboardKey::KEY_R) {
-            self.reset_fuild();
+            self.journal.apply(&mut self.simulation, SceneInput::Reset);
         }
         if rl_handle.is_key_pressed(KeyboardKey::KEY_V) {
-            self.send_vel = !self.send_vel;
+            self.journal.apply(&mut self.simulation, SceneInput::ToggleInflow);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_S) {
+            save_snapshot(&self.simulation, self.journal.steps(), Path::new(SNAPSHOT_PATH));
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_L) {
+            let input = SceneInput::restore_snapshot(SNAPSHOT_PATH);
+            self.journal.apply(&mut self.simulation, input);
         }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_N) {
+            export_npy(&self.simulation, Path::new("advection"));
+        }
+        self.view.update(rl_handle, rl_thread, self.renderer.texture());
+        handle_mouse_inputs(
+            rl_handle,
+            &mut self.wall_editor,
+            &mut self.journal,
+            &mut self.simulation,
+            &self.view,
+        );
+        handle_journal_keys(rl_handle, &mut self.journal, &mut self.simulation, Path::new(JOURNAL_PATH));
 
         if rl_handle.is_key_pressed(KeyboardKey::KEY_SPACE) || BYPASS {
-            if self.send_vel {
-                for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                    self.fluid_domain.fluid_grid[0][y_id].velocity.0 = 10f64;
-                    self.fluid_domain.fluid_grid[self.fluid_domain.fluid_grid.len() - 1][y_id]
-                        .velocity
-                        .0 = 10f64;
-                }
-            } else {
-                for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                    self.fluid_domain.fluid_grid[0][y_id].velocity.0 = 0f64;
-                    self.fluid_domain.fluid_grid[self.fluid_domain.fluid_grid.len() - 1][y_id]
-                        .velocity
-                        .0 = 0f64;
-                }
-            }
-
-            for x_id in 1..self.fluid_domain.fluid_grid.len() - 1 {
-                for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                    self.fluid_domain.fluid_grid[x_id][y_id].pressure = 0.0;
-                }
-            }
-
-            self.fluid_domain.solve_grid_incompressibility();
-            self.fluid_domain.apply_advection();
+            self.journal.step(&mut self.simulation);
+            self.renderer.advance(self.simulation.fluid_domain(), self.journal.steps());
         }
-        self.update_image_to_draw(self.value_to_display);
+        self.renderer.update(rl_handle, self.simulation.fluid_domain(), &self.view);
     }
 
     fn draw(&mut self, rl_handle: &mut RaylibDrawHandle) {
-        let arr: Vec<u8> = self
-            .render_image
-            .get_image_data()
-            .iter()
-            .flat_map(|c| c.color_to_int().to_be_bytes())
-            .collect();
-        self.render_texture.update_texture(&arr);
-        rl_handle.draw_texture(
-            &self.render_texture,
-            (rl_handle.get_screen_width() - GRID_SIZE.0 as i32) / 2,
-            (rl_handle.get_screen_height() - GRID_SIZE.1 as i32) / 2,
-            COLOR_WHITE,
-        );
+        self.renderer.draw(rl_handle, self.simulation.fluid_domain(), &self.view);
+        draw_wall_editor(rl_handle, &self.wall_editor, &self.view);
 
-        if rl_handle.gui_dropdown_box(
-            Rectangle::new(10.0, 10.0, 150.0, 30.0),
-            Some(CStr::from_bytes_with_nul(b"Velocity X;Velocity Y;Pressure\0").unwrap()),
-            &mut self.dropdown_select,
-            self.dropdown_edit_mode,
-        ) {
-            self.dropdown_edit_mode = !self.dropdown_edit_mode;
-        }
-        self.value_to_display = match self.dropdown_select {
-            0 => ValueToDisplay::VelocityX,
-            1 => ValueToDisplay::VelocityY,
-            2 => ValueToDisplay::Pressure,
-            _ => ValueToDisplay::VelocityX,
-        };
+        self.renderer.draw_controls(rl_handle);
+    }
+
+    fn fluid_domain(&self) -> &FluidDomain {
+        self.simulation.fluid_domain()
+    }
+
+    fn steps(&self) -> usize {
+        self.journal.steps()
+    }
+
+    fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.renderer.diagnostics()
+    }
+
+    fn render_pixels(&mut self) -> &PixelBuffer {
+        self.renderer.pixels(&self.simulation.fluid_domain)
     }
 }
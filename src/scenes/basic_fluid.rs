@@ -1,63 +1,61 @@
-use crate::colors::*;
-use crate::scenes::Scene;
+use crate::input_journal::{InputJournal, SceneInput};
+use crate::field_image::{DisplayMode, PixelBuffer};
+use crate::field_renderer::{DisplaySettings, FieldRenderer};
+use crate::scene_description::DisplayField;
+use crate::scenes::{
+    draw_wall_editor, export_npy, handle_journal_keys, handle_mouse_inputs, save_snapshot, Scene,
+};
+use crate::simulation::Simulation;
+use crate::view_camera::ViewCamera;
+use crate::wall_editor::WallEditor;
 use crate::fluid_engine::*;
 use raylib::prelude::*;
+use std::path::Path;
 
 const GRID_SIZE: (usize, usize) = (256, 128);
 const TIMESTEP: f64 = 0.1;
+const SNAPSHOT_PATH: &str = "basic.snapshot";
+const JOURNAL_PATH: &str = "basic.journal";
 
-pub struct BasicFuildScene {
-    fluid_domain: FluidDomain<{GRID_SIZE.0}, {GRID_SIZE.1}>,
-    render_image: Image,
-    render_texture: Texture2D,
+pub struct BasicFluidSimulation {
+    pub fluid_domain: FluidDomain,
 }
-impl BasicFuildScene {
-    pub fn new(rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) -> Self {
-        let mut image = Image::gen_image_color(GRID_SIZE.0 as i32, GRID_SIZE.1 as i32, Color::new(0, 0, 0, 0));
-        let mut fluid_domain = FluidDomain::new(TIMESTEP);
+impl BasicFluidSimulation {
+    pub fn new() -> Self {
+        let mut fluid_domain = FluidDomain::new(GRID_SIZE.0, GRID_SIZE.1, TIMESTEP);
 
-        let wall_color = Color::new(0, 0, 0, 0);
         // Set static wall as boundary
         for x_id in 0..GRID_SIZE.0 {
             fluid_domain.set_cell_state(x_id, GRID_SIZE.1 - 1, CellState::Wall);
-            image.draw_pixel(x_id as i32, (GRID_SIZE.1 - 1) as i32, wall_color);
         }
         for y_id in 1..(GRID_SIZE.1 - 1) {
             fluid_domain.set_cell_state(0, y_id, CellState::Wall);
             fluid_domain.set_cell_state(GRID_SIZE.0 - 1, y_id, CellState::Wall);
-            image.draw_pixel(0, y_id as i32, wall_color);
-            image.draw_pixel((GRID_SIZE.0 - 1) as i32, y_id as i32, wall_color);
         }
 
-        BasicFuildScene {
-            fluid_domain,
-            render_texture: rl_handle
-                .load_texture_from_image(rl_thread, &image)
-                .unwrap(),
-            render_image: image,
-        }
+        BasicFluidSimulation { fluid_domain }
     }
 }
 
-impl Scene for BasicFuildScene {
-    fn get_title(&self) -> &str {
-        "Basic 2D fluid"
+impl Simulation for BasicFluidSimulation {
+    fn fluid_domain(&self) -> &FluidDomain {
+        &self.fluid_domain
     }
 
-    fn has_background(&self) -> bool {
-        false
+    fn fluid_domain_mut(&mut self) -> &mut FluidDomain {
+        &mut self.fluid_domain
     }
 
-    fn help_text(&self) -> Vec<&str> {
-        vec![]
+    fn reset(&mut self) {
+        *self = Self::new();
     }
 
-    fn update(&mut self, _rl_handle: &mut RaylibHandle) {
+    fn step(&mut self) {
         // Updating velocity based on external force
         for line_id in 1..self.fluid_domain.fluid_grid.len() - 1 {
             for column_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
                 self.fluid_domain.fluid_grid[line_id][column_id].velocity.1 -=
-                    ((self.fluid_domain.fluid_grid[line_id][column_id - 1].state as u8) as f64)
+                    self.fluid_domain.fluid_grid[line_id][column_id - 1].state.flow_factor()
                         * TIMESTEP
                         * 9.81f64;
                 self.fluid_domain.fluid_grid[line_id][column_id].pressure = 0.0;
@@ -66,48 +64,99 @@ impl Scene for BasicFuildScene {
 
         self.fluid_domain.solve_grid_incompressibility();
         self.fluid_domain.apply_advection();
+    }
+}
 
-        let (mut min_pressure_in_grid, mut max_pressure_in_grid) = (f64::MAX, 0.0);
-        for x_id in 1..self.fluid_domain.fluid_grid.len() - 1 {
-            for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                if min_pressure_in_grid > self.fluid_domain.fluid_grid[x_id][y_id].pressure {
-                    min_pressure_in_grid = self.fluid_domain.fluid_grid[x_id][y_id].pressure;
-                }
-                if max_pressure_in_grid < self.fluid_domain.fluid_grid[x_id][y_id].pressure {
-                    max_pressure_in_grid = self.fluid_domain.fluid_grid[x_id][y_id].pressure;
-                }
-            }
+pub struct BasicFuildScene {
+    simulation: BasicFluidSimulation,
+    journal: InputJournal,
+    wall_editor: WallEditor,
+    view: ViewCamera,
+    renderer: FieldRenderer,
+}
+impl BasicFuildScene {
+    pub fn new(rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) -> Self {
+        let display = DisplaySettings::new(DisplayMode::Field(DisplayField::Pressure));
+
+        BasicFuildScene {
+            simulation: BasicFluidSimulation::new(),
+            journal: InputJournal::new("basic"),
+            wall_editor: WallEditor::new(),
+            view: ViewCamera::new(),
+            renderer: FieldRenderer::new(rl_handle, rl_thread, GRID_SIZE, display, "basic").unwrap(),
         }
+    }
+}
 
-        for x_id in 1..self.fluid_domain.fluid_grid.len() - 1 {
-            for y_id in 1..self.fluid_domain.fluid_grid[0].len() - 1 {
-                let pressure_level = (self.fluid_domain.fluid_grid[x_id][y_id].pressure - min_pressure_in_grid)
-                    / (max_pressure_in_grid - min_pressure_in_grid);
-                let color = if self.fluid_domain.fluid_grid[x_id][y_id].state == CellState::Wall {
-                    Color::new(0, 0, 0, 255)
-                } else {
-                    hsl_to_rgb((1.0 - pressure_level) / 6.0, 1.0, 1.0)
-                };
+impl Scene for BasicFuildScene {
+    fn get_title(&self) -> &str {
+        "Basic 2D fluid"
+    }
 
-                self.render_image
-                    .draw_pixel(x_id as i32, y_id as i32, color);
+    fn has_background(&self) -> bool {
+        false
+    }
 
-            }
+    fn help_text(&self) -> Vec<&str> {
+        let mut help_text = vec!["Left drag: push the fluid along the mouse"];
+        help_text.extend(WallEditor::help_text());
+        help_text.extend(FieldRenderer::help_text());
+        help_text.extend([
+            "S: save a snapshot to basic.snapshot",
+            "L: restore the snapshot from basic.snapshot",
+            "N: write the fields as .npy arrays to basic_npy_<index>",
+            "F5: save the input journal to basic.journal",
+            "F6: replay basic.journal from the start",
+        ]);
+        help_text
+    }
+
+    fn update(&mut self, rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_S) {
+            save_snapshot(&self.simulation, self.journal.steps(), Path::new(SNAPSHOT_PATH));
         }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_L) {
+            let input = SceneInput::restore_snapshot(SNAPSHOT_PATH);
+            self.journal.apply(&mut self.simulation, input);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_N) {
+            export_npy(&self.simulation, Path::new("basic"));
+        }
+        self.view.update(rl_handle, rl_thread, self.renderer.texture());
+        handle_mouse_inputs(
+            rl_handle,
+            &mut self.wall_editor,
+            &mut self.journal,
+            &mut self.simulation,
+            &self.view,
+        );
+        handle_journal_keys(rl_handle, &mut self.journal, &mut self.simulation, Path::new(JOURNAL_PATH));
 
-        // Copy data to shader
-        println!("min_value: {}", min_pressure_in_grid);
-        println!("max_value: {}", max_pressure_in_grid);
+        self.journal.step(&mut self.simulation);
+        self.renderer.advance(self.simulation.fluid_domain(), self.journal.steps());
+        self.renderer.update(rl_handle, self.simulation.fluid_domain(), &self.view);
     }
 
     fn draw(&mut self, rl_handle: &mut RaylibDrawHandle) {
-        let arr: Vec<u8> = self
-            .render_image
-            .get_image_data()
-            .iter()
-            .flat_map(|c| c.color_to_int().to_be_bytes())
-            .collect();
-        self.render_texture.update_texture(&arr);
-        rl_handle.draw_texture(&self.render_texture, (rl_handle.get_screen_width() - GRID_SIZE.0 as i32) / 2, (rl_handle.get_screen_height() - GRID_SIZE.1 as i32) / 2, COLOR_WHITE);
+        self.renderer.draw(rl_handle, self.simulation.fluid_domain(), &self.view);
+        draw_wall_editor(rl_handle, &self.wall_editor, &self.view);
+
+        self.renderer.draw_controls(rl_handle);
+    }
+
+    fn fluid_domain(&self) -> &FluidDomain {
+        self.simulation.fluid_domain()
+    }
+
+    fn steps(&self) -> usize {
+        self.journal.steps()
+    }
+
+    fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.renderer.diagnostics()
+    }
+
+    fn render_pixels(&mut self) -> &PixelBuffer {
+        self.renderer.pixels(&self.simulation.fluid_domain)
     }
 }
@@ -0,0 +1,285 @@
+use crate::colors::*;
+use crate::field_image::{ColorScale, DisplayMode, PixelBuffer};
+use crate::field_renderer::{DisplaySettings, FieldRenderer};
+use crate::fluid_engine::*;
+use crate::input_journal::{InputJournal, SceneInput};
+use crate::logging::EXPORT;
+use crate::scene_description::DisplayField;
+use crate::scenes::{
+    draw_wall_editor, export_npy, handle_journal_keys, handle_mouse_inputs, Scene,
+};
+use crate::simulation::Simulation;
+use crate::view_camera::ViewCamera;
+use crate::wall_editor::WallEditor;
+use raylib::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Same tank as the "Basic 2D fluid" scene
+const GRID_SIZE: (usize, usize) = (256, 128);
+const TIMESTEP: f64 = 0.01;
+const GRAVITY: f64 = 9.81;
+/// Width `a` of the water column in cells, its height is `2a` (Martin & Moyce n² = 2 case)
+const COLUMN_WIDTH: usize = 48;
+const PARTICLES_PER_CELL_AXIS: usize = 2;
+const PARTICLE_SPACING: f64 = DEFAULT_GRID_SPACING / PARTICLES_PER_CELL_AXIS as f64;
+/// The walls around the tank are one cell thick
+const WALL_THICKNESS: f64 = DEFAULT_GRID_SPACING;
+/// Between the inner faces of the left and right walls
+const TANK_LENGTH: f64 = GRID_SIZE.0 as f64 * DEFAULT_GRID_SPACING - 2.0 * WALL_THICKNESS;
+const FRONT_CSV_PATH: &str = "dam_break_front.csv";
+/// The front history also stops after this many steps (100 s), if walls drawn keep the surge
+/// from reaching the right wall
+const MAX_FRONT_SAMPLES: usize = 10_000;
+const JOURNAL_PATH: &str = "dam_break.journal";
+
+struct FrontSample {
+    step: usize,
+    time: f64,
+    front_position: f64,
+}
+
+pub struct DamBreakSimulation {
+    pub fluid_domain: FluidDomain,
+    pub liquid: LiquidParticles,
+    step: usize,
+    /// Recorded until the surge reaches the right wall, where the Martin & Moyce comparison ends
+    front_history: Vec<FrontSample>,
+}
+impl DamBreakSimulation {
+    pub fn new() -> Self {
+        let mut fluid_domain = FluidDomain::new(GRID_SIZE.0, GRID_SIZE.1, TIMESTEP);
+        // Set static wall as boundary (floor is the last row, the grid y axis points down)
+        for x_id in 0..GRID_SIZE.0 {
+            fluid_domain.set_cell_state(x_id, GRID_SIZE.1 - 1, CellState::Wall);
+        }
+        for y_id in 1..(GRID_SIZE.1 - 1) {
+            fluid_domain.set_cell_state(0, y_id, CellState::Wall);
+            fluid_domain.set_cell_state(GRID_SIZE.0 - 1, y_id, CellState::Wall);
+        }
+
+        // Water column resting against the left wall
+        let mut liquid = LiquidParticles::new((0.0, GRAVITY));
+        liquid.fill_rectangle(
+            &fluid_domain,
+            1..1 + COLUMN_WIDTH,
+            GRID_SIZE.1 - 1 - 2 * COLUMN_WIDTH..GRID_SIZE.1 - 1,
+            PARTICLES_PER_CELL_AXIS,
+        );
+
+        DamBreakSimulation {
+            fluid_domain,
+            liquid,
+            step: 0,
+            front_history: Vec::new(),
+        }
+    }
+
+    /// Surge front position measured from the inner face of the left wall. The leading particle
+    /// stands for the liquid up to half a particle spacing ahead of it, so the front starts on
+    /// the face of the column (Z = 1).
+    fn front_position(&self) -> f64 {
+        self.liquid.front_position() + 0.5 * PARTICLE_SPACING - WALL_THICKNESS
+    }
+
+    /// True once the front is less than a particle spacing from the inner face of the right wall
+    fn front_reached_right_wall(&self) -> bool {
+        self.front_history
+            .last()
+            .is_some_and(|sample| sample.front_position > TANK_LENGTH - PARTICLE_SPACING)
+    }
+
+    /// Martin & Moyce dimensionless time T = t * sqrt(2g / a).
+    fn dimensionless_time(time: f64) -> f64 {
+        time * (2.0 * GRAVITY / (COLUMN_WIDTH as f64 * DEFAULT_GRID_SPACING)).sqrt()
+    }
+
+    /// Martin & Moyce dimensionless front position Z = z / a.
+    fn dimensionless_front(front_position: f64) -> f64 {
+        front_position / (COLUMN_WIDTH as f64 * DEFAULT_GRID_SPACING)
+    }
+
+    pub fn export_front_history(&self, path: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "step,time,front_position,T,Z")?;
+        for sample in self.front_history.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                sample.step,
+                sample.time,
+                sample.front_position,
+                Self::dimensionless_time(sample.time),
+                Self::dimensionless_front(sample.front_position)
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+impl Simulation for DamBreakSimulation {
+    fn fluid_domain(&self) -> &FluidDomain {
+        &self.fluid_domain
+    }
+
+    fn fluid_domain_mut(&mut self) -> &mut FluidDomain {
+        &mut self.fluid_domain
+    }
+
+    fn restore(&mut self, _fluid_domain: FluidDomain, _scene_state: &[f64]) -> Result<(), String> {
+        Err("Snapshots do not hold the liquid particles of the dam break".to_string())
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn apply_impulse(&mut self, position: (f64, f64), velocity: (f64, f64), radius: f64) {
+        // The grid velocities are rebuilt from the particles at every step
+        self.liquid.apply_velocity_impulse(position, velocity, radius);
+    }
+
+    fn step(&mut self) {
+        self.liquid.step(&mut self.fluid_domain);
+        self.step += 1;
+        if self.front_history.len() < MAX_FRONT_SAMPLES && !self.front_reached_right_wall() {
+            self.front_history.push(FrontSample {
+                step: self.step,
+                time: self.step as f64 * TIMESTEP,
+                front_position: self.front_position(),
+            });
+        }
+    }
+}
+
+pub struct DamBreakScene {
+    simulation: DamBreakSimulation,
+    journal: InputJournal,
+    wall_editor: WallEditor,
+    view: ViewCamera,
+    renderer: FieldRenderer,
+}
+impl DamBreakScene {
+    pub fn new(rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) -> Self {
+        // Speed keeps a fixed scale, twice the shallow water surge speed, so frames compare
+        let max_speed = 2.0 * (GRAVITY * (2 * COLUMN_WIDTH) as f64 * DEFAULT_GRID_SPACING).sqrt();
+        let display = DisplaySettings::with_color_scale(
+            DisplayMode::Field(DisplayField::Speed),
+            ColorScale::locked(Colormap::Viridis, (0.0, max_speed)),
+        );
+
+        DamBreakScene {
+            simulation: DamBreakSimulation::new(),
+            journal: InputJournal::new("dam-break"),
+            wall_editor: WallEditor::new(),
+            view: ViewCamera::new(),
+            renderer: FieldRenderer::new(rl_handle, rl_thread, GRID_SIZE, display, "dam_break").unwrap(),
+        }
+    }
+}
+
+impl Scene for DamBreakScene {
+    fn get_title(&self) -> &str {
+        "Dam break"
+    }
+
+    fn has_background(&self) -> bool {
+        false
+    }
+
+    fn help_text(&self) -> Vec<&str> {
+        let mut help_text = vec!["Left drag: push the fluid along the mouse"];
+        help_text.extend(WallEditor::help_text());
+        help_text.extend(FieldRenderer::help_text());
+        help_text.extend([
+            "R: release the water column again",
+            "E: export the surge front history, up to the right wall, to dam_break_front.csv",
+            "CSV columns: step, time, front_position, T = t*sqrt(2g/a), Z = z/a",
+            "N: write the fields as .npy arrays to dam_break_npy_<index>",
+            "F5: save the input journal to dam_break.journal",
+            "F6: replay dam_break.journal from the start",
+        ]);
+        help_text
+    }
+
+    fn update(&mut self, rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_R) {
+            self.journal.apply(&mut self.simulation, SceneInput::Reset);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_E) {
+            match self.simulation.export_front_history(FRONT_CSV_PATH) {
+                Ok(()) => log::info!(target: EXPORT, "Front history written to {}", FRONT_CSV_PATH),
+                Err(error) => log::error!(target: EXPORT, "Unable to write {}: {}", FRONT_CSV_PATH, error),
+            }
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_N) {
+            export_npy(&self.simulation, Path::new("dam_break"));
+        }
+        self.view.update(rl_handle, rl_thread, self.renderer.texture());
+        handle_mouse_inputs(
+            rl_handle,
+            &mut self.wall_editor,
+            &mut self.journal,
+            &mut self.simulation,
+            &self.view,
+        );
+        handle_journal_keys(rl_handle, &mut self.journal, &mut self.simulation, Path::new(JOURNAL_PATH));
+
+        self.journal.step(&mut self.simulation);
+        self.renderer.advance(self.simulation.fluid_domain(), self.journal.steps());
+        self.renderer.update(rl_handle, self.simulation.fluid_domain(), &self.view);
+    }
+
+    fn draw(&mut self, rl_handle: &mut RaylibDrawHandle) {
+        self.renderer.draw(rl_handle, self.simulation.fluid_domain(), &self.view);
+        draw_wall_editor(rl_handle, &self.wall_editor, &self.view);
+
+        if let Some(sample) = self.simulation.front_history.last() {
+            let front_text = format!(
+                "T = {:.2}  Z = {:.2}",
+                DamBreakSimulation::dimensionless_time(sample.time),
+                DamBreakSimulation::dimensionless_front(sample.front_position)
+            );
+            rl_handle.draw_text(front_text.as_str(), 10, 50, 18, COLOR_LIGHT);
+        }
+
+        self.renderer.draw_controls(rl_handle);
+    }
+
+    fn fluid_domain(&self) -> &FluidDomain {
+        self.simulation.fluid_domain()
+    }
+
+    fn steps(&self) -> usize {
+        self.journal.steps()
+    }
+
+    fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.renderer.diagnostics()
+    }
+
+    fn render_pixels(&mut self) -> &PixelBuffer {
+        self.renderer.pixels(&self.simulation.fluid_domain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn front_starts_on_the_column_face_and_advances() {
+        let mut simulation = DamBreakSimulation::new();
+        let mut front = DamBreakSimulation::dimensionless_front(simulation.front_position());
+        assert!((front - 1.0).abs() < 1e-9, "Z(0) = {}", front);
+        for _ in 0..30 {
+            simulation.step();
+            let next_front = DamBreakSimulation::dimensionless_front(simulation.front_position());
+            assert!(next_front >= front, "Z went back from {} to {}", front, next_front);
+            front = next_front;
+        }
+        assert!(front > 1.0);
+        assert_eq!(simulation.front_history.len(), 30);
+    }
+}
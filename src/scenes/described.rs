@@ -0,0 +1,281 @@
+use crate::field_image::{DisplayMode, PixelBuffer};
+use crate::field_renderer::{DisplaySettings, FieldRenderer};
+use crate::fluid_engine::*;
+use crate::scene_description::*;
+use crate::input_journal::{InputJournal, SceneInput};
+use crate::scenes::{
+    draw_wall_editor, export_npy, handle_journal_keys, handle_mouse_inputs, save_snapshot, Scene,
+};
+use crate::simulation::Simulation;
+use crate::view_camera::ViewCamera;
+use crate::wall_editor::WallEditor;
+use raylib::prelude::*;
+use std::path::{Path, PathBuf};
+
+const LIQUID_PARTICLES_PER_CELL_AXIS: usize = 2;
+/// Dye concentration injected by the blue pixels of a mask
+const MASK_SOURCE_DYE: f64 = 1.0;
+
+struct SourceCell {
+    x_id: usize,
+    y_id: usize,
+    dye: Option<f64>,
+    velocity: Option<[f64; 2]>,
+}
+
+/// Simulation built from a `SceneDescription`
+pub struct DescribedSimulation {
+    pub description: SceneDescription,
+    pub fluid_domain: FluidDomain,
+    pub liquid: Option<LiquidParticles>,
+    source_cells: Vec<SourceCell>,
+}
+impl DescribedSimulation {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Ok(Self::new(SceneDescription::load(path)?))
+    }
+
+    pub fn new(description: SceneDescription) -> Self {
+        let fluid_domain = description.build_domain();
+        let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+
+        let mut source_cells = Vec::new();
+        let mut liquid = None;
+        for x_id in 0..grid_size_x {
+            for y_id in 0..grid_size_y {
+                if fluid_domain.fluid_grid[x_id][y_id].state == CellState::Wall {
+                    continue;
+                }
+                let (x, y) = description.cell_center(x_id, y_id);
+                for source in description.sources.iter() {
+                    if source.region.contains(x, y) {
+                        source_cells.push(SourceCell {
+                            x_id,
+                            y_id,
+                            dye: source.dye,
+                            velocity: source.velocity,
+                        });
+                    }
+                }
+                if description.liquid.iter().any(|region| region.contains(x, y)) {
+                    let gravity = (description.forces.gravity[0], description.forces.gravity[1]);
+                    liquid
+                        .get_or_insert_with(|| LiquidParticles::new(gravity))
+                        .fill_cell(&fluid_domain, x_id, y_id, LIQUID_PARTICLES_PER_CELL_AXIS);
+                }
+            }
+        }
+
+        if let Some(domain_mask) = &description.domain_mask {
+            for (x_id, y_id) in domain_mask.dye_source_cells() {
+                source_cells.push(SourceCell {
+                    x_id,
+                    y_id,
+                    dye: Some(MASK_SOURCE_DYE),
+                    velocity: None,
+                });
+            }
+        }
+
+        DescribedSimulation {
+            description,
+            fluid_domain,
+            liquid,
+            source_cells,
+        }
+    }
+
+    fn apply_sources(&mut self) {
+        for source_cell in self.source_cells.iter() {
+            let cell = &mut self.fluid_domain.fluid_grid[source_cell.x_id][source_cell.y_id];
+            if let Some(dye) = source_cell.dye {
+                cell.dye = dye;
+            }
+            if let Some(velocity) = source_cell.velocity {
+                cell.velocity = (velocity[0], velocity[1]);
+            }
+        }
+    }
+}
+
+impl Simulation for DescribedSimulation {
+    fn fluid_domain(&self) -> &FluidDomain {
+        &self.fluid_domain
+    }
+
+    fn fluid_domain_mut(&mut self) -> &mut FluidDomain {
+        &mut self.fluid_domain
+    }
+
+    fn restore(&mut self, fluid_domain: FluidDomain, _scene_state: &[f64]) -> Result<(), String> {
+        if self.liquid.is_some() {
+            return Err("Snapshots do not hold liquid particles".to_string());
+        }
+        self.check_snapshot_grid(&fluid_domain)?;
+        self.fluid_domain = fluid_domain;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.description.clone());
+    }
+
+    fn apply_impulse(&mut self, position: (f64, f64), velocity: (f64, f64), radius: f64) {
+        match self.liquid.as_mut() {
+            Some(liquid) => liquid.apply_velocity_impulse(position, velocity, radius),
+            None => self
+                .fluid_domain
+                .apply_velocity_impulse(position, velocity, radius),
+        }
+    }
+
+    fn step(&mut self) {
+        self.apply_sources();
+
+        if let Some(liquid) = self.liquid.as_mut() {
+            liquid.step(&mut self.fluid_domain);
+            return;
+        }
+
+        let gravity = self.description.forces.gravity;
+        self.fluid_domain.apply_body_force((gravity[0], gravity[1]));
+        self.fluid_domain.apply_boundary_conditions();
+        for column in self.fluid_domain.fluid_grid.iter_mut() {
+            for cell in column.iter_mut() {
+                cell.pressure = 0.0;
+            }
+        }
+        self.fluid_domain.solve_grid_incompressibility();
+        self.fluid_domain.apply_advection();
+    }
+}
+
+/// Generic scene displaying any `SceneDescription`
+pub struct DescribedScene {
+    simulation: DescribedSimulation,
+    paused: bool,
+    /// Next to the scene file, with a `.snapshot` extension
+    snapshot_path: PathBuf,
+    journal: InputJournal,
+    wall_editor: WallEditor,
+    view: ViewCamera,
+    renderer: FieldRenderer,
+}
+impl DescribedScene {
+    pub fn load(rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread, path: &Path) -> Result<Self, String> {
+        let simulation = DescribedSimulation::load(path)?;
+        let display = DisplaySettings::with_color_scale(
+            DisplayMode::Field(simulation.description.display.field),
+            simulation.description.display.color_scale(),
+        );
+
+        let mut renderer = FieldRenderer::new(
+            rl_handle,
+            rl_thread,
+            simulation.fluid_domain.grid_size(),
+            display,
+            &path.with_extension("").to_string_lossy(),
+        )?;
+        if !simulation.description.display.contour_levels.is_empty() {
+            renderer.set_contour_levels(simulation.description.display.contour_levels.clone());
+        }
+
+        Ok(DescribedScene {
+            simulation,
+            paused: false,
+            snapshot_path: path.with_extension("snapshot"),
+            journal: InputJournal::new(&path.to_string_lossy()),
+            wall_editor: WallEditor::new(),
+            view: ViewCamera::new(),
+            renderer,
+        })
+    }
+}
+
+impl Scene for DescribedScene {
+    fn get_title(&self) -> &str {
+        &self.simulation.description.title
+    }
+
+    fn has_background(&self) -> bool {
+        false
+    }
+
+    fn help_text(&self) -> Vec<&str> {
+        let mut help_text = vec!["Left drag: push the fluid along the mouse"];
+        help_text.extend(WallEditor::help_text());
+        help_text.extend(FieldRenderer::help_text());
+        help_text.extend([
+            "R: reset the scene",
+            "SPACE: pause / resume the simulation",
+            "S: save a snapshot next to the scene file",
+            "L: restore the snapshot saved next to the scene file",
+            "N: write the fields as .npy arrays next to the scene file",
+            "F5: save the input journal next to the scene file",
+            "F6: replay the input journal saved next to the scene file",
+        ]);
+        help_text
+    }
+
+    fn update(&mut self, rl_handle: &mut RaylibHandle, rl_thread: &RaylibThread) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_R) {
+            self.journal.apply(&mut self.simulation, SceneInput::Reset);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_SPACE) {
+            self.paused = !self.paused;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_S) {
+            save_snapshot(&self.simulation, self.journal.steps(), &self.snapshot_path);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_L) {
+            let input = SceneInput::restore_snapshot(&self.snapshot_path);
+            self.journal.apply(&mut self.simulation, input);
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_N) {
+            export_npy(&self.simulation, &self.snapshot_path.with_extension(""));
+        }
+        self.view.update(rl_handle, rl_thread, self.renderer.texture());
+        handle_mouse_inputs(
+            rl_handle,
+            &mut self.wall_editor,
+            &mut self.journal,
+            &mut self.simulation,
+            &self.view,
+        );
+        handle_journal_keys(
+            rl_handle,
+            &mut self.journal,
+            &mut self.simulation,
+            &self.snapshot_path.with_extension("journal"),
+        );
+
+        if !self.paused {
+            self.journal.step(&mut self.simulation);
+            self.renderer.advance(self.simulation.fluid_domain(), self.journal.steps());
+        }
+        self.renderer.update(rl_handle, self.simulation.fluid_domain(), &self.view);
+    }
+
+    fn draw(&mut self, rl_handle: &mut RaylibDrawHandle) {
+        self.renderer.draw(rl_handle, self.simulation.fluid_domain(), &self.view);
+        draw_wall_editor(rl_handle, &self.wall_editor, &self.view);
+
+        self.renderer.draw_controls(rl_handle);
+    }
+
+    fn fluid_domain(&self) -> &FluidDomain {
+        self.simulation.fluid_domain()
+    }
+
+    fn steps(&self) -> usize {
+        self.journal.steps()
+    }
+
+    fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.renderer.diagnostics()
+    }
+
+    fn render_pixels(&mut self) -> &PixelBuffer {
+        self.renderer.pixels(&self.simulation.fluid_domain)
+    }
+}
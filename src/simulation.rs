@@ -0,0 +1,47 @@
+use crate::fluid_engine::FluidDomain;
+use crate::input_journal::SceneInput;
+
+/// Window-free part of a scene: everything needed to advance the fluid, so it can run
+/// both inside a scene and from the headless runner.
+pub trait Simulation {
+    fn fluid_domain(&self) -> &FluidDomain;
+    fn fluid_domain_mut(&mut self) -> &mut FluidDomain;
+    /// Advance the simulation by one timestep
+    fn step(&mut self);
+    /// Go back to the initial state
+    fn reset(&mut self);
+    /// Scene specific inputs, `Reset`, `RestoreSnapshot` and `Impulse` are handled by the journal
+    fn apply_input(&mut self, _input: &SceneInput) {}
+    /// Gaussian velocity impulse around `position`, see `FluidDomain::apply_velocity_impulse`
+    fn apply_impulse(&mut self, position: (f64, f64), velocity: (f64, f64), radius: f64) {
+        self.fluid_domain_mut()
+            .apply_velocity_impulse(position, velocity, radius);
+    }
+
+    /// Scene state saved in snapshots along with the domain, such as the inflow toggle
+    fn scene_state(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    /// Replace the domain by a snapshot taken from the same scene, `scene_state` being the one
+    /// saved with it
+    fn restore(&mut self, fluid_domain: FluidDomain, _scene_state: &[f64]) -> Result<(), String> {
+        self.check_snapshot_grid(&fluid_domain)?;
+        *self.fluid_domain_mut() = fluid_domain;
+        Ok(())
+    }
+
+    /// Error unless a snapshot of `fluid_domain` fits the grid of the scene
+    fn check_snapshot_grid(&self, fluid_domain: &FluidDomain) -> Result<(), String> {
+        if fluid_domain.grid_size() != self.fluid_domain().grid_size() {
+            return Err(format!(
+                "Snapshot grid is {}x{} but the scene grid is {}x{}",
+                fluid_domain.grid_size().0,
+                fluid_domain.grid_size().1,
+                self.fluid_domain().grid_size().0,
+                self.fluid_domain().grid_size().1
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use crate::colors::*;
+use raylib::prelude::*;
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 64.0;
+/// Zoom factor for one notch of the mouse wheel
+const WHEEL_ZOOM_STEP: f32 = 1.1;
+
+/// 2D camera showing a scene's grid texture, one world unit per cell.
+///
+/// Every mouse interaction with the grid goes through `screen_to_grid` so that
+/// impulses and wall edits land under the cursor whatever the pan and zoom.
+pub struct ViewCamera {
+    camera: Camera2D,
+    /// Keep the whole grid visible and centred, also when the window is resized
+    fit_to_window: bool,
+    bilinear_filtering: bool,
+}
+impl ViewCamera {
+    pub fn new() -> Self {
+        ViewCamera {
+            camera: Camera2D {
+                offset: Vector2::zero(),
+                target: Vector2::zero(),
+                rotation: 0.0,
+                zoom: 1.0,
+            },
+            fit_to_window: true,
+            bilinear_filtering: false,
+        }
+    }
+
+    /// Size of a cell on screen, in pixels
+    pub fn zoom(&self) -> f32 {
+        self.camera.zoom
+    }
+
+    /// Camera mapping grid cells to screen pixels, for drawing the grid in another mode
+    pub fn camera(&self) -> Camera2D {
+        self.camera
+    }
+
+    pub fn bilinear_filtering(&self) -> bool {
+        self.bilinear_filtering
+    }
+
+    /// Mouse wheel zooms around the cursor, middle drag pans, `F` fits the grid to the window
+    /// and `B` switches `texture` between nearest and bilinear filtering.
+    pub fn update(&mut self, rl_handle: &RaylibHandle, rl_thread: &RaylibThread, texture: &Texture2D) {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_F) {
+            self.fit_to_window = true;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_B) {
+            self.bilinear_filtering = !self.bilinear_filtering;
+            texture.set_texture_filter(
+                rl_thread,
+                if self.bilinear_filtering {
+                    TextureFilter::TEXTURE_FILTER_BILINEAR
+                } else {
+                    TextureFilter::TEXTURE_FILTER_POINT
+                },
+            );
+        }
+
+        let wheel_move = rl_handle.get_mouse_wheel_move();
+        if wheel_move != 0.0 {
+            // Keep the grid point under the cursor in place
+            let mouse_position = rl_handle.get_mouse_position();
+            let (grid_x, grid_y) = self.screen_to_grid(mouse_position);
+            self.camera.offset = mouse_position;
+            self.camera.target = Vector2::new(grid_x as f32, grid_y as f32);
+            self.camera.zoom = (self.camera.zoom * WHEEL_ZOOM_STEP.powf(wheel_move)).clamp(MIN_ZOOM, MAX_ZOOM);
+            self.fit_to_window = false;
+        }
+        if rl_handle.is_mouse_button_down(MouseButton::MOUSE_BUTTON_MIDDLE) {
+            let mouse_delta = rl_handle.get_mouse_delta();
+            if mouse_delta.x != 0.0 || mouse_delta.y != 0.0 {
+                self.camera.target -= mouse_delta / self.camera.zoom;
+                self.fit_to_window = false;
+            }
+        }
+
+        if self.fit_to_window {
+            let screen_width = rl_handle.get_screen_width() as f32;
+            let screen_height = rl_handle.get_screen_height() as f32;
+            let texture_width = texture.width() as f32;
+            let texture_height = texture.height() as f32;
+            self.camera.offset = Vector2::new(screen_width / 2.0, screen_height / 2.0);
+            self.camera.target = Vector2::new(texture_width / 2.0, texture_height / 2.0);
+            self.camera.zoom = (screen_width / texture_width)
+                .min(screen_height / texture_height)
+                .clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
+
+    /// Grid position (in cells) under a screen position
+    pub fn screen_to_grid(&self, position: Vector2) -> (f64, f64) {
+        let world = (position - self.camera.offset) / self.camera.zoom + self.camera.target;
+        (world.x as f64, world.y as f64)
+    }
+
+    /// Screen position of a grid position (in cells), inverse of `screen_to_grid`
+    pub fn grid_to_screen(&self, position: (f64, f64)) -> Vector2 {
+        let world = Vector2::new(position.0 as f32, position.1 as f32);
+        (world - self.camera.target) * self.camera.zoom + self.camera.offset
+    }
+
+    /// Draw `texture` at the grid origin through the camera
+    pub fn draw_texture(&self, rl_handle: &mut RaylibDrawHandle, texture: &Texture2D) {
+        let mut rl_mode_handle = rl_handle.begin_mode2D(self.camera);
+        rl_mode_handle.draw_texture(texture, 0, 0, COLOR_WHITE);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::colors::*;
+use crate::fluid_engine::*;
+use crate::input_journal::SceneInput;
+use raylib::prelude::*;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EditorTool {
+    Brush,
+    Line,
+    Rectangle,
+}
+
+/// Cells changed by an edit with their new states, or their previous ones in an undo entry
+type CellStates = Vec<(usize, usize, CellState)>;
+
+struct Stroke {
+    /// Right button: walls are turned back into fluid
+    erase: bool,
+    start: (isize, isize),
+    last: (isize, isize),
+    /// State of every cell changed by the brush before the stroke, for undo
+    previous_states: CellStates,
+}
+
+/// Paints (left button) and erases (right button) walls over the rendered grid.
+///
+/// Edits are returned as `SceneInput::SetCellStates` so they go through the input journal;
+/// only fluid/air cells become walls and only walls become fluid, inflow and outflow cells
+/// and the outer border are never changed.
+pub struct WallEditor {
+    pub enabled: bool,
+    pub tool: EditorTool,
+    /// 0 paints a single cell
+    pub brush_radius: usize,
+    stroke: Option<Stroke>,
+    undo_stack: Vec<CellStates>,
+    /// `InputJournal::domain_generation` the undo entries were recorded against
+    domain_generation: usize,
+}
+impl WallEditor {
+    pub fn new() -> Self {
+        WallEditor {
+            enabled: false,
+            tool: EditorTool::Brush,
+            brush_radius: 2,
+            stroke: None,
+            undo_stack: Vec::new(),
+            domain_generation: 0,
+        }
+    }
+
+    /// Keys and mouse buttons of the editor, for the scene help pages
+    pub fn help_text() -> Vec<&'static str> {
+        vec![
+            "W: toggle the wall editor (left drag paints walls, right drag erases)",
+            "1 / 2 / 3: wall editor brush, line or rectangle, [ / ]: brush size",
+            "Z: undo the last wall edit",
+        ]
+    }
+
+    /// Drop the undo entries and the stroke in progress when the domain was replaced since
+    /// they were recorded, or when `discard` is set (edits ignored by a replay)
+    pub fn track_domain(&mut self, domain_generation: usize, discard: bool) {
+        if discard || domain_generation != self.domain_generation {
+            self.undo_stack.clear();
+            self.stroke = None;
+            self.domain_generation = domain_generation;
+        }
+    }
+
+    /// Handle the editor keys and mouse, `mouse_cell` is the grid position under the mouse (in cells).
+    pub fn update(
+        &mut self,
+        rl_handle: &RaylibHandle,
+        fluid_domain: &FluidDomain,
+        mouse_cell: (f64, f64),
+    ) -> Vec<SceneInput> {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_W) {
+            self.enabled = !self.enabled;
+            self.stroke = None;
+        }
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_ONE) {
+            self.tool = EditorTool::Brush;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_TWO) {
+            self.tool = EditorTool::Line;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_THREE) {
+            self.tool = EditorTool::Rectangle;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+            self.brush_radius += 1;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) {
+            self.brush_radius = self.brush_radius.saturating_sub(1);
+        }
+
+        let mut inputs = Vec::new();
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_Z) && self.stroke.is_none() {
+            if let Some(previous_states) = self.undo_stack.pop() {
+                inputs.push(SceneInput::SetCellStates(previous_states));
+            }
+        }
+
+        let cell = (mouse_cell.0.floor() as isize, mouse_cell.1.floor() as isize);
+        if self.stroke.is_none() {
+            let erase = rl_handle.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT);
+            if erase || rl_handle.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+                self.stroke = Some(Stroke {
+                    erase,
+                    start: cell,
+                    last: cell,
+                    previous_states: Vec::new(),
+                });
+            }
+        }
+        let Some(mut stroke) = self.stroke.take() else {
+            return inputs;
+        };
+
+        let button = if stroke.erase {
+            MouseButton::MOUSE_BUTTON_RIGHT
+        } else {
+            MouseButton::MOUSE_BUTTON_LEFT
+        };
+        let released = !rl_handle.is_mouse_button_down(button);
+        let target_cells = match self.tool {
+            EditorTool::Brush => self.line_cells(stroke.last, cell),
+            EditorTool::Line if released => self.line_cells(stroke.start, cell),
+            EditorTool::Rectangle if released => rectangle_cells(stroke.start, cell),
+            _ => Vec::new(),
+        };
+        stroke.last = cell;
+
+        let (new_states, previous_states) = changed_cells(fluid_domain, &target_cells, stroke.erase);
+        if !new_states.is_empty() {
+            inputs.push(SceneInput::SetCellStates(new_states));
+            stroke.previous_states.extend(previous_states);
+        }
+
+        if released {
+            if !stroke.previous_states.is_empty() {
+                self.undo_stack.push(stroke.previous_states);
+            }
+        } else {
+            self.stroke = Some(stroke);
+        }
+        inputs
+    }
+
+    /// Brush outline, line or rectangle preview and editor status, `grid_to_screen` maps
+    /// grid positions (in cells) to the screen.
+    pub fn draw(&self, rl_handle: &mut RaylibDrawHandle, grid_to_screen: impl Fn((f64, f64)) -> Vector2, cell_size: f32) {
+        if !self.enabled {
+            return;
+        }
+        let mouse_position = rl_handle.get_mouse_position();
+        rl_handle.draw_circle_lines(
+            mouse_position.x as i32,
+            mouse_position.y as i32,
+            (self.brush_radius as f32 + 0.5) * cell_size,
+            COLOR_YELLOW,
+        );
+
+        if let Some(stroke) = &self.stroke {
+            let start = grid_to_screen((stroke.start.0 as f64 + 0.5, stroke.start.1 as f64 + 0.5));
+            let end = grid_to_screen((stroke.last.0 as f64 + 0.5, stroke.last.1 as f64 + 0.5));
+            let color = if stroke.erase { COLOR_RED } else { COLOR_YELLOW };
+            match self.tool {
+                EditorTool::Brush => {}
+                EditorTool::Line => rl_handle.draw_line_v(start, end, color),
+                EditorTool::Rectangle => rl_handle.draw_rectangle_lines_ex(
+                    Rectangle::new(
+                        start.x.min(end.x),
+                        start.y.min(end.y),
+                        (end.x - start.x).abs(),
+                        (end.y - start.y).abs(),
+                    ),
+                    1.0,
+                    color,
+                ),
+            }
+        }
+
+        let tool_name = match self.tool {
+            EditorTool::Brush => "brush",
+            EditorTool::Line => "line",
+            EditorTool::Rectangle => "rectangle",
+        };
+        let status_text = format!(
+            "WALL EDITOR: {} (size {}), undo: {}",
+            tool_name,
+            self.brush_radius,
+            self.undo_stack.len()
+        );
+        let screen_height = rl_handle.get_screen_height();
+        rl_handle.draw_text(status_text.as_str(), 10, screen_height - 30, 18, COLOR_YELLOW);
+    }
+
+    /// Brush stamped along the segment between two cells
+    fn line_cells(&self, start: (isize, isize), end: (isize, isize)) -> Vec<(isize, isize)> {
+        let sample_count = (end.0 - start.0).abs().max((end.1 - start.1).abs()).max(1);
+        let radius = self.brush_radius as isize;
+        let mut cells = Vec::new();
+        for sample_id in 0..=sample_count {
+            let t = sample_id as f64 / sample_count as f64;
+            let center_x = (start.0 as f64 + t * (end.0 - start.0) as f64).round() as isize;
+            let center_y = (start.1 as f64 + t * (end.1 - start.1) as f64).round() as isize;
+            for offset_x in -radius..=radius {
+                for offset_y in -radius..=radius {
+                    if offset_x * offset_x + offset_y * offset_y <= radius * radius {
+                        cells.push((center_x + offset_x, center_y + offset_y));
+                    }
+                }
+            }
+        }
+        cells
+    }
+}
+
+fn rectangle_cells(corner: (isize, isize), opposite_corner: (isize, isize)) -> Vec<(isize, isize)> {
+    let mut cells = Vec::new();
+    for x_id in corner.0.min(opposite_corner.0)..=corner.0.max(opposite_corner.0) {
+        for y_id in corner.1.min(opposite_corner.1)..=corner.1.max(opposite_corner.1) {
+            cells.push((x_id, y_id));
+        }
+    }
+    cells
+}
+
+/// New and previous states of the interior cells the edit actually changes
+fn changed_cells(fluid_domain: &FluidDomain, cells: &[(isize, isize)], erase: bool) -> (CellStates, CellStates) {
+    let (grid_size_x, grid_size_y) = fluid_domain.grid_size();
+    let mut visited = HashSet::new();
+    let (mut new_states, mut previous_states) = (Vec::new(), Vec::new());
+    for &(x_id, y_id) in cells {
+        if x_id < 1 || y_id < 1 || x_id >= grid_size_x as isize - 1 || y_id >= grid_size_y as isize - 1 {
+            continue;
+        }
+        let (x_id, y_id) = (x_id as usize, y_id as usize);
+        if !visited.insert((x_id, y_id)) {
+            continue;
+        }
+        let state = fluid_domain.fluid_grid[x_id][y_id].state;
+        let new_state = match (erase, state) {
+            (false, CellState::Fluid | CellState::Air) => CellState::Wall,
+            (true, CellState::Wall) => CellState::Fluid,
+            _ => continue,
+        };
+        new_states.push((x_id, y_id, new_state));
+        previous_states.push((x_id, y_id, state));
+    }
+    (new_states, previous_states)
+}